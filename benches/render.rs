@@ -0,0 +1,40 @@
+//! Renders `reference_scene` on one thread and reports rays per second,
+//! so changes to the tracer can be measured before and after.
+//! Run with `cargo bench`.
+
+use lib::reference_scene;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 10;
+
+fn main() {
+    let (camera, scene) = reference_scene();
+    let camera = camera.threads(1).build();
+
+    // the first render pays for page faults and cold caches
+    let image = camera.render_to_buffer(&scene).expect("reference render failed");
+    // seeded, so every run traces exactly these rays
+    let stats = *image.stats().expect("render recorded no statistics");
+
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            camera.render_to_buffer(&scene).expect("reference render failed");
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    let median = times[RUNS / 2];
+
+    println!(
+        "reference scene, {}x{}: median {:.1} ms, min {:.1} ms over {} runs, {:.0} rays/s, {:.2} bounces per path, {:.1} BVH nodes per ray",
+        image.width(),
+        image.height(),
+        median.as_secs_f64() * 1e3,
+        times[0].as_secs_f64() * 1e3,
+        RUNS,
+        stats.rays() as f64 / median.as_secs_f64(),
+        stats.average_depth(),
+        stats.bvh_nodes_per_ray(),
+    );
+}
@@ -0,0 +1,110 @@
+use crate::ray::{Ray};
+use crate::vec3::{Point};
+
+/// Minimum thickness given to flat boxes.
+pub const AABB_PADDING: f64 = 1e-4;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    min: Point,
+    max: Point,
+}
+
+impl Aabb {
+    /// Box containing nothing; the identity for `surrounding`.
+    pub const EMPTY: Aabb = Aabb {
+        min: Point::new([f64::INFINITY; 3]),
+        max: Point::new([f64::NEG_INFINITY; 3]),
+    };
+
+    /// Box containing all of space, for unbounded primitives such as planes.
+    pub const UNIVERSE: Aabb = Aabb {
+        min: Point::new([f64::NEG_INFINITY; 3]),
+        max: Point::new([f64::INFINITY; 3]),
+    };
+
+    /// Builds the box spanned by two opposite corners, in any order.
+    pub fn new(a: Point, b: Point) -> Aabb {
+        let mut min = a;
+        let mut max = a;
+        for i in 0..3 {
+            min[i] = a[i].min(b[i]);
+            max[i] = a[i].max(b[i]);
+        }
+        Aabb { min, max }
+    }
+
+    pub fn min(&self) -> &Point {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Point {
+        &self.max
+    }
+
+    /// Smallest box enclosing both boxes.
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        let mut min = self.min;
+        let mut max = self.max;
+        for i in 0..3 {
+            min[i] = self.min[i].min(other.min[i]);
+            max[i] = self.max[i].max(other.max[i]);
+        }
+        Aabb { min, max }
+    }
+
+    /// Widens every axis thinner than `delta` to exactly `delta`, so flat
+    /// geometry such as an axis-aligned triangle still has a volume.
+    pub fn padded(&self, delta: f64) -> Aabb {
+        let mut min = self.min;
+        let mut max = self.max;
+        for i in 0..3 {
+            if max[i] - min[i] < delta {
+                let mid = (min[i] + max[i]) / 2.0;
+                min[i] = mid - delta / 2.0;
+                max[i] = mid + delta / 2.0;
+            }
+        }
+        Aabb { min, max }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Index of the axis along which the box is widest.
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test: whether the ray passes through the box within `[t_min, t_max]`.
+    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direct()[axis];
+            let mut t0 = (self.min[axis] - ray.org()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.org()[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN (0 * inf on a slab boundary) must not widen the interval
+            if t0 > t_min { t_min = t0; }
+            if t1 < t_max { t_max = t1; }
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
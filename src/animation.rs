@@ -0,0 +1,190 @@
+use crate::camera::{Band, Camera, ProgressCallback, ProgressMeter, RenderError, RenderOutcome, PROGRESS_INTERVAL, TILE_SIZE};
+use crate::color::{Color, BLACK};
+use crate::image::{Image};
+use crate::scene::{Scene};
+use crate::stats::{StatsCollector};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, mpsc, atomic::{AtomicUsize, Ordering}};
+use std::thread::{self, ScopedJoinHandle};
+use std::time::Instant;
+
+/// One frame of an animation: its camera and what it looks at.
+pub type Frame = (Camera, Scene);
+
+/// A band of one frame for a worker to render.
+struct Job {
+    frame: Arc<Frame>,
+    band_index: usize,
+    len: usize,
+}
+
+/// A rendered band, and whether it was finished before a cancellation.
+type BandResult = (usize, Vec<Color>, Vec<u16>, bool);
+
+/// Renders `frames` frames, asking `scene` for the camera and scene of
+/// each, and writes them to `output_dir` as `frame_0000.png`,
+/// `frame_0001.png`, ... (`.ppm` without the `png` feature, or the
+/// extension of the camera's output format if it sets one).
+///
+/// One pool of worker threads, sized by the first frame's camera, renders
+/// the whole sequence. Progress covers all frames and goes to the first
+/// camera's progress callback if it has one, otherwise to stderr unless
+/// that camera is quiet. If a frame's camera is cancelled, that frame is
+/// written partially and the sequence stops there.
+pub fn render_animation<F>(frames: usize, output_dir: impl AsRef<Path>, scene: F) -> Result<RenderOutcome, RenderError>
+where
+    F: Fn(usize) -> Frame,
+{
+    let output_dir = output_dir.as_ref();
+    if frames == 0 {
+        return Ok(RenderOutcome::Completed);
+    }
+    fs::create_dir_all(output_dir).map_err(RenderError::Create)?;
+
+    let now = Instant::now();
+    let first = scene(0);
+    let num_threads = first.0.threads();
+    let progress = first.0.progress_callback().cloned();
+    let quiet = first.0.is_quiet();
+    let mut meter = ProgressMeter::new(now, frames * first.0.pixel_count());
+    let counter = AtomicUsize::new(0);
+    let stats = StatsCollector::default();
+
+    let (job_sender, job_receiver) = mpsc::channel::<Job>();
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<BandResult>();
+
+    let result = thread::scope(|scope| {
+        let mut handles = vec![];
+        for _ in 0..num_threads {
+            let result_sender = result_sender.clone();
+            let (job_receiver, counter, stats) = (&job_receiver, &counter, &stats);
+            handles.push(scope.spawn(move || {
+                stats.start_thread();
+                loop {
+                    let job = job_receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    // the queue closes once every frame has been handed out
+                    let Ok(job) = job else { break };
+                    let (camera, frame_scene) = &*job.frame;
+                    let mut band = vec![BLACK; job.len];
+                    let mut band_counts = vec![0; job.len];
+                    let y0 = job.band_index * TILE_SIZE;
+                    let buffers = Band { y0, pixels: &mut band, counts: &mut band_counts, hits: None };
+                    let finished = camera.render_band(0..camera.image_width(), buffers, frame_scene, counter);
+                    stats.flush();
+                    if result_sender.send((job.band_index, band, band_counts, finished)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        drop(result_sender);
+
+        let mut rendered = 0;
+        let mut next = Some(first);
+        let mut result = Ok(RenderOutcome::Completed);
+        for index in 0..frames {
+            let frame = Arc::new(next.take().unwrap_or_else(|| scene(index)));
+            let total = frame.0.pixel_count();
+            // later frames may differ in size; assume the rest match this one
+            meter.set_total(rendered + (frames - index) * total);
+
+            let report_frame = |completed| report(&progress, quiet, &mut meter, completed);
+            result = render_frame(&frame, &job_sender, &result_receiver, &handles, &counter, report_frame)
+                .and_then(|(image, outcome)| write_frame(&frame.0, &image, output_dir, index).map(|_| outcome));
+            if !matches!(result, Ok(RenderOutcome::Completed)) {
+                break;
+            }
+            rendered += total;
+        }
+        // let idle workers exit before the scope joins them
+        drop(job_sender);
+
+        let mut panicked = false;
+        for handle in handles {
+            panicked |= handle.join().is_err();
+        }
+        if panicked { Err(RenderError::ThreadPanicked) } else { result }
+    });
+
+    if matches!(result, Ok(RenderOutcome::Completed)) {
+        report(&progress, quiet, &mut meter, counter.load(Ordering::SeqCst));
+        if !quiet {
+            eprintln!("\nRendering time: {}s\n{}", now.elapsed().as_secs(), stats.finish(now.elapsed()));
+        }
+    }
+    result
+}
+
+/// Queues every band of `frame` for the pool and assembles the results,
+/// `Cancelled` if a cancellation left any band unfinished.
+fn render_frame(
+    frame: &Arc<Frame>,
+    jobs: &mpsc::Sender<Job>,
+    results: &mpsc::Receiver<BandResult>,
+    workers: &[ScopedJoinHandle<()>],
+    counter: &AtomicUsize,
+    mut report: impl FnMut(usize),
+) -> Result<(Image, RenderOutcome), RenderError> {
+    let camera = &frame.0;
+    let (width, height) = (camera.image_width(), camera.image_height());
+    let total = width * height;
+    let band_len = (TILE_SIZE * width).max(1);
+    let band_count = total.div_ceil(band_len);
+    for band_index in 0..band_count {
+        let len = band_len.min(total - band_index * band_len);
+        let job = Job { frame: Arc::clone(frame), band_index, len };
+        jobs.send(job).map_err(|_| RenderError::ThreadPanicked)?;
+    }
+
+    let mut pixels = vec![BLACK; total];
+    let mut sample_counts = vec![0; total];
+    let mut received = 0;
+    let mut outcome = RenderOutcome::Completed;
+    while received < band_count {
+        match results.recv_timeout(PROGRESS_INTERVAL) {
+            Ok((band_index, band, band_counts, finished)) => {
+                if !finished {
+                    outcome = RenderOutcome::Cancelled;
+                }
+                let start = band_index * band_len;
+                pixels[start..start + band.len()].copy_from_slice(&band);
+                sample_counts[start..start + band.len()].copy_from_slice(&band_counts);
+                received += 1;
+            },
+            // a worker only finishes early by panicking
+            Err(mpsc::RecvTimeoutError::Timeout) if !workers.iter().any(|w| w.is_finished()) => {},
+            Err(_) => return Err(RenderError::ThreadPanicked),
+        }
+        report(counter.load(Ordering::SeqCst));
+    }
+
+    camera.fill_skipped_rows(&mut pixels, width);
+    camera.fill_skipped_rows(&mut sample_counts, width);
+    let image = camera.downsample(Image::new(width, height, pixels).with_sample_counts(sample_counts));
+    Ok((image, outcome))
+}
+
+fn write_frame(camera: &Camera, image: &Image, output_dir: &Path, index: usize) -> Result<(), RenderError> {
+    // frames are PNG unless the build can't write it
+    let default = if cfg!(feature = "png") { "png" } else { "ppm" };
+    let name = format!("frame_{:04}.{}", index, default);
+    let extension = camera.output_format_for(Path::new(&name))?.extension();
+    let path = output_dir.join(name).with_extension(extension);
+    let file = BufWriter::new(File::create(&path).map_err(RenderError::Create)?);
+    camera.write_image(image, &path, file)
+}
+
+fn report(progress: &Option<ProgressCallback>, quiet: bool, meter: &mut ProgressMeter, completed: usize) {
+    match progress {
+        Some(progress) => progress(meter.fraction(completed)),
+        None if quiet => {},
+        None => {
+            // trailing spaces wipe leftovers of a longer previous line
+            eprint!("\r{}   ", meter.update(completed));
+            let _ = std::io::stderr().flush();
+        },
+    }
+}
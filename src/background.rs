@@ -0,0 +1,104 @@
+use crate::color::{Color, WHITE};
+use crate::texture::{ImageTexture, TextureError};
+use crate::image::{Image};
+use crate::vec3::{Vec3};
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+const SKY_BLUE: Color = Color::new([0.5, 0.7, 1.0]);
+
+/// Radiance seen by rays that leave the scene without hitting anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    SolidColor(Color),
+    /// Blends from the first color straight down to the second straight up,
+    /// following the `y` component of the ray direction.
+    VerticalGradient(Color, Color),
+    /// Image-based lighting from an equirectangular map.
+    Environment(EnvMap),
+}
+
+impl Default for Background {
+    /// The white-to-sky-blue gradient the renderer has always used.
+    fn default() -> Background {
+        Background::VerticalGradient(WHITE, SKY_BLUE)
+    }
+}
+
+impl Background {
+    pub fn color(&self, direction: &Vec3) -> Color {
+        match self {
+            Background::SolidColor(color) => *color,
+            Background::VerticalGradient(bottom, top) => {
+                let alpha = (direction.unit().y() + 1.0) / 2.0;
+                (1.0 - alpha) * *bottom + alpha * *top
+            },
+            Background::Environment(map) => map.color(direction),
+        }
+    }
+}
+
+/// Equirectangular environment map lighting the scene from all around.
+/// The image center is seen looking down `-z`, `+x` a quarter turn to its
+/// right and `+y` at the top row, matching `Projection::Equirectangular`,
+/// so a panorama rendered with the default camera orientation maps back
+/// onto the same directions. Cheap to clone; the image is shared.
+#[derive(Clone)]
+pub struct EnvMap {
+    texture: Arc<ImageTexture>,
+    intensity: f64,
+}
+
+impl EnvMap {
+    pub fn new(image: Image) -> EnvMap {
+        EnvMap {
+            texture: Arc::new(ImageTexture::new_linear(image)),
+            intensity: 1.0,
+        }
+    }
+
+    /// Loads a Radiance `.hdr` map, or any other format `ImageTexture` reads.
+    pub fn load(path: impl AsRef<Path>) -> Result<EnvMap, TextureError> {
+        let texture = ImageTexture::load(path)?;
+        Ok(EnvMap {
+            texture: Arc::new(texture),
+            intensity: 1.0,
+        })
+    }
+
+    /// Scales the map's radiance.
+    pub fn with_intensity(mut self, intensity: f64) -> EnvMap {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let d = direction.unit();
+        let longitude = d.x().atan2(-d.z());
+        let latitude = d.y().clamp(-1.0, 1.0).asin();
+        let u = longitude / (2.0 * PI) + 0.5;
+        let v = latitude / PI + 0.5;
+        // the left and right edges meet behind the viewer, at +z
+        self.intensity * self.texture.value_wrapped(u, v)
+    }
+}
+
+impl fmt::Debug for EnvMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let image = self.texture.image();
+        f.debug_struct("EnvMap")
+            .field("width", &image.width())
+            .field("height", &image.height())
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+impl PartialEq for EnvMap {
+    /// Maps are equal when they share the same image and intensity.
+    fn eq(&self, other: &EnvMap) -> bool {
+        Arc::ptr_eq(&self.texture, &other.texture) && self.intensity == other.intensity
+    }
+}
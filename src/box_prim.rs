@@ -0,0 +1,54 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::quad::{Quad};
+use crate::world::{World};
+use std::sync::{Arc};
+
+/// Axis-aligned box made of six quads whose normals point outward.
+/// (Named to stay clear of `std::boxed::Box`.)
+pub struct BoxPrim {
+    sides: World,
+}
+
+impl BoxPrim {
+    /// Builds the box spanned by two opposite corners, in any order.
+    pub fn new(a: Point, b: Point, m: Arc<dyn Material>) -> BoxPrim {
+        let bounds = Aabb::new(a, b);
+        let (min, max) = (*bounds.min(), *bounds.max());
+
+        let dx = Vec3::new([max.x() - min.x(), 0.0, 0.0]);
+        let dy = Vec3::new([0.0, max.y() - min.y(), 0.0]);
+        let dz = Vec3::new([0.0, 0.0, max.z() - min.z()]);
+
+        let mut sides = World::new();
+        // each face's u x v points away from the box
+        sides.add(Arc::new(Quad::new(Point::new([min.x(), min.y(), max.z()]), dx, dy, Arc::clone(&m))));
+        sides.add(Arc::new(Quad::new(Point::new([max.x(), min.y(), max.z()]), -dz, dy, Arc::clone(&m))));
+        sides.add(Arc::new(Quad::new(Point::new([max.x(), min.y(), min.z()]), -dx, dy, Arc::clone(&m))));
+        sides.add(Arc::new(Quad::new(Point::new([min.x(), min.y(), min.z()]), dz, dy, Arc::clone(&m))));
+        sides.add(Arc::new(Quad::new(Point::new([min.x(), max.y(), max.z()]), dx, -dz, Arc::clone(&m))));
+        sides.add(Arc::new(Quad::new(Point::new([min.x(), min.y(), min.z()]), dx, dz, Arc::clone(&m))));
+
+        BoxPrim { sides }
+    }
+}
+
+impl Hittable for BoxPrim {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.sides.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.sides.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        self.sides.pdf_value(origin, direction, t_min)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.sides.random(origin)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::aabb::{Aabb};
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::stats;
+use std::sync::{Arc};
+
+/// Bounding volume hierarchy over a set of objects. Rays only test the
+/// objects whose boxes they pass through, making intersection roughly
+/// logarithmic in the object count.
+pub struct Bvh {
+    root: Option<BvhNode>,
+}
+
+enum BvhNode {
+    Leaf(Arc<dyn Hittable>),
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl Bvh {
+    pub fn new(objects: &[Arc<dyn Hittable>]) -> Bvh {
+        let mut objects: Vec<(Aabb, Arc<dyn Hittable>)> = objects.iter()
+            .map(|object| (object.bounding_box(), Arc::clone(object)))
+            .filter(|(bbox, _)| !bbox.is_empty())
+            .collect();
+        let root = if objects.is_empty() { None } else { Some(build(&mut objects)) };
+        Bvh { root }
+    }
+}
+
+/// Splits at the median centroid along the axis where the centroids spread widest.
+fn build(objects: &mut [(Aabb, Arc<dyn Hittable>)]) -> BvhNode {
+    if objects.len() == 1 {
+        return BvhNode::Leaf(Arc::clone(&objects[0].1));
+    }
+
+    let bbox = objects.iter().fold(Aabb::EMPTY, |acc, (b, _)| acc.surrounding(b));
+    let centroids = objects.iter()
+        .fold(Aabb::EMPTY, |acc, (b, _)| acc.surrounding(&Aabb::new(b.centroid(), b.centroid())));
+    let axis = centroids.longest_axis();
+
+    // Unbounded objects have NaN centroids; total_cmp sorts them to the
+    // end, so they still land in some leaf.
+    let mid = objects.len() / 2;
+    objects.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+        a.centroid()[axis].total_cmp(&b.centroid()[axis])
+    });
+    let (left, right) = objects.split_at_mut(mid);
+
+    BvhNode::Branch {
+        bbox,
+        left: Box::new(build(left)),
+        right: Box::new(build(right)),
+    }
+}
+
+impl BvhNode {
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf(object) => object.bounding_box(),
+            BvhNode::Branch { bbox, .. } => *bbox,
+        }
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        stats::count_bvh_node();
+        match self {
+            BvhNode::Leaf(object) => object.intersect(ray, t_min, t_max),
+            BvhNode::Branch { bbox, left, right } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.intersect(ray, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |rec| rec.t());
+                right.intersect(ray, t_min, closest).or(left_hit)
+            },
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.root.as_ref()?.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.root.as_ref().map_or(Aabb::EMPTY, |root| root.bounding_box())
+    }
+}
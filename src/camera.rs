@@ -1,215 +1,1796 @@
 use crate::ray::{Ray, Hittable};
 use crate::vec3::{Point, Vec3};
 use crate::color::*;
+use crate::background::Background;
+use crate::scene::{Scene};
+use crate::image::{Image, OutputFormat, PpmFormat};
+use crate::random::{self, random_f64};
+use crate::checkpoint::Accumulation;
+use crate::stats::{StatsCollector};
+use crate::config::{RenderConfig};
+#[cfg(feature = "denoise")]
+use crate::denoise::Denoiser;
 use std::fs::File;
 use std::io::{Write, BufWriter};
-use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}};
+#[cfg(not(feature = "rayon"))]
+use std::sync::Mutex;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "rayon")]
+use std::panic::{self, AssertUnwindSafe};
 use std::thread;
+use std::f64::consts::PI;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use std::{fmt, io};
 
 const ASPECT_RATIO: f64 = 16.0 / 9.0;
 const V_FOV: f64 = 20.0;    // vertical field of view
-const WIDTH: f64 = 1920.0;
-const THREADS_NUM: i64 = 12;
-const SAMPLE_NUM: u16 = 500;
-const REFLECT_DEPTH: u8 = 20;
+pub(crate) const WIDTH: u32 = 1920;
+pub(crate) const TILE_SIZE: usize = 32;
+const ADAPTIVE_BATCH: u16 = 16;
+pub(crate) const SAMPLE_NUM: u16 = 500;
 const FOCUS_DIST: f64 = 10.0;
 const DEFOCUS_ANGLE: f64 = 0.6;
+// full-frame 35 mm film is 24 mm tall
+const SENSOR_HEIGHT: f64 = 24.0;
+const MM_PER_METER: f64 = 1000.0;
+pub(crate) const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const PROGRESS_WINDOW: Duration = Duration::from_secs(5);
+
+/// Receives the completed fraction of a render, from 0.0 to 1.0.
+pub type ProgressCallback = Arc<dyn Fn(f64) + Send + Sync>;
+
+#[derive(Debug)]
+pub enum RenderError {
+    Create(io::Error),
+    Write(io::Error),
+    /// The checkpoint to resume from could not be read.
+    Resume(io::Error),
+    Checkpoint(io::Error),
+    ThreadPanicked,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Create(e) => write!(f, "could not create output file: {}", e),
+            RenderError::Write(e) => write!(f, "could not write output file: {}", e),
+            RenderError::Resume(e) => write!(f, "could not resume from checkpoint: {}", e),
+            RenderError::Checkpoint(e) => write!(f, "could not write checkpoint: {}", e),
+            RenderError::ThreadPanicked => write!(f, "a render thread panicked"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Create(e) | RenderError::Write(e) | RenderError::Resume(e) | RenderError::Checkpoint(e) => Some(e),
+            RenderError::ThreadPanicked => None,
+        }
+    }
+}
+
+/// How a render that returned without error ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderOutcome {
+    Completed,
+    /// The cancel flag stopped the render before it was done; the output
+    /// holds the pixels finished until then and black elsewhere. A flag
+    /// raised after the last pixel leaves the render `Completed`.
+    Cancelled,
+}
+
+/// A render with its auxiliary output variables (AOVs), each a separate
+/// image that can be written like any other. Pixels whose camera rays
+/// escape the scene are 0 in every AOV.
+pub struct RenderOutput {
+    /// The beauty render.
+    pub color: Image,
+    /// Distance to the first hit in world units, in every channel. Write
+    /// it as HDR to keep values above 1.
+    pub depth: Image,
+    /// World-space normal `n` at the first hit, stored as `0.5 * n + 0.5`,
+    /// so only misses are black.
+    pub normal: Image,
+    /// Base color of the first surface hit.
+    pub albedo: Image,
+}
+
+/// Shape of the lens opening, which is the shape out-of-focus highlights
+/// (bokeh) take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    /// Regular polygon with this many blades, one vertex pointing up;
+    /// fewer than 3 falls back to a circle.
+    Polygon(u32),
+}
+
+/// How rays leave the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Rays fan out from the eye through the viewport, framed by `v_fov`.
+    Perspective,
+    /// Rays run parallel to the view direction from points spread over a
+    /// viewport of the given world-space height; `v_fov` and defocus are
+    /// ignored.
+    Orthographic(f64),
+    /// Full 360 x 180 degree panorama: columns map to longitude and rows
+    /// to latitude around the view direction. The image is always 2:1;
+    /// `height`, `aspect_ratio`, `v_fov` and defocus are ignored.
+    Equirectangular,
+}
+
+/// How sample positions are spread over a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplingStrategy {
+    /// Independent uniform jitter for every sample.
+    #[default]
+    Random,
+    /// One jittered sample per cell of a `sqrt(n) x sqrt(n)` grid; samples
+    /// left over when `n` is not a perfect square are drawn uniformly.
+    Stratified,
+}
+
+/// How the samples of a pixel are weighted by their offset from its
+/// center. Wider filters draw samples from beyond the pixel's own square,
+/// which trades a little sharpness for smoother edges.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PixelFilter {
+    /// Equal weights over the pixel's own square.
+    #[default]
+    Box,
+    /// Weight falling linearly to zero `radius` pixels from the center
+    /// along each axis; 1.0 is the usual choice.
+    Tent { radius: f64 },
+    /// Gaussian with a standard deviation of a third of `radius`, lowered
+    /// to reach zero at `radius`; 1.5 is the usual choice.
+    Gaussian { radius: f64 },
+}
+
+impl PixelFilter {
+    /// Half the width of the square samples are drawn from.
+    fn radius(&self) -> f64 {
+        match *self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent { radius } | PixelFilter::Gaussian { radius } => radius.max(0.5),
+        }
+    }
+
+    /// Weight of a sample `(dx, dy)` pixels from the center.
+    fn weight(&self, dx: f64, dy: f64) -> f64 {
+        let radius = self.radius();
+        let axis = |d: f64| match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent { .. } => (1.0 - d.abs() / radius).max(0.0),
+            PixelFilter::Gaussian { .. } => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(d) - gaussian(radius)).max(0.0)
+            },
+        };
+        axis(dx) * axis(dy)
+    }
+}
+
+/// Photographic lens; see `CameraBuilder::lens`.
+#[derive(Debug, Clone, Copy)]
+struct Lens {
+    focal_length: f64,
+    f_number: f64,
+}
+
+impl Lens {
+    /// Vertical field of view and defocus angle in degrees for a sensor
+    /// `sensor_height` millimeters tall, focused at `focus_dist` scene
+    /// units, of which there are `units_per_meter` to the meter.
+    fn angles(&self, sensor_height: f64, focus_dist: f64, units_per_meter: f64) -> (f64, f64) {
+        let v_fov = 2.0 * (sensor_height / (2.0 * self.focal_length)).atan();
+        // aperture diameter is the focal length over the f-number
+        let aperture_radius = self.focal_length / (2.0 * self.f_number) / MM_PER_METER * units_per_meter;
+        let defocus_angle = 2.0 * (aperture_radius / focus_dist).atan();
+        (v_fov.to_degrees(), defocus_angle.to_degrees())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Adaptive {
+    threshold: f64,
+    min_samples: u16,
+    max_samples: u16,
+}
 
 pub struct Camera {
     eye: Point,
+    projection: Projection,
+    vup: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
     width: f64,
     height: f64,
+    viewport_width: f64,
+    viewport_height: f64,
+    viewport_dist: f64,
+    defocus_radius: f64,
     pixel_start: Point,
     delta_u: Vec3,
     delta_v: Vec3,
     sample_num: u16,
-    reflect_depth: u8,
+    sampling: SamplingStrategy,
+    filter: PixelFilter,
+    supersample: usize,
+    strata: u16,
+    adaptive: Option<Adaptive>,
+    trace: TraceSettings,
     defocus_angle: f64,
+    aperture: Aperture,
+    shutter: (f64, f64),
     disk_u: Vec3,
     disk_v: Vec3,
+    ppm_format: PpmFormat,
+    output_format: Option<OutputFormat>,
+    output: OutputSettings,
+    threads: usize,
+    progress: Option<ProgressCallback>,
+    quiet: bool,
+    cancel: Option<Arc<AtomicBool>>,
+    seed: Option<u64>,
+    checkpoint: Option<(PathBuf, Duration)>,
+    resume: Option<PathBuf>,
+    time_budget: Option<Duration>,
+    #[cfg(feature = "denoise")]
+    denoiser: Option<Denoiser>,
+    transparent_background: bool,
+    skip_empty_tiles: bool,
+    preview_stride: usize,
+}
+
+/// Collects the camera settings; every field defaults to the value
+/// the renderer has always used, so `CameraBuilder::new().build()`
+/// matches the original fixed configuration.
+#[derive(Clone)]
+pub struct CameraBuilder {
+    look_from: Point,
+    look_at: Point,
+    width: u32,
+    height: Option<u32>,
+    aspect_ratio: f64,
+    v_fov: f64,
+    samples: u16,
+    max_depth: u8,
+    terminal_color: Color,
+    shadow_epsilon: Option<f64>,
+    world_scale: f64,
+    focus_dist: Option<f64>,
+    focus_point: Option<Point>,
+    defocus_angle: f64,
+    lens: Option<Lens>,
+    sensor_height: f64,
+    aperture: Aperture,
+    shutter: (f64, f64),
+    ppm_format: PpmFormat,
+    output_format: Option<OutputFormat>,
+    threads: Option<usize>,
+    progress: Option<ProgressCallback>,
+    quiet: bool,
+    cancel: Option<Arc<AtomicBool>>,
+    seed: Option<u64>,
+    vup: Vec3,
+    projection: Projection,
+    sampling: SamplingStrategy,
+    filter: PixelFilter,
+    supersample: usize,
+    adaptive: Option<Adaptive>,
+    russian_roulette: Option<u8>,
+    clamp_indirect: Option<f64>,
+    path_regularization: f64,
+    background: Background,
+    transparent_background: bool,
+    skip_empty_tiles: bool,
+    preview_stride: usize,
+    lights: Vec<Arc<dyn Hittable>>,
+    output: OutputSettings,
+    checkpoint: Option<(PathBuf, Duration)>,
+    resume: Option<PathBuf>,
+    time_budget: Option<Duration>,
+    #[cfg(feature = "denoise")]
+    denoiser: Option<Denoiser>,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> CameraBuilder {
+        CameraBuilder {
+            look_from: Point::new([0.0, 0.0, 0.0]),
+            look_at: Point::new([0.0, 0.0, -1.0]),
+            width: WIDTH,
+            height: None,
+            aspect_ratio: ASPECT_RATIO,
+            v_fov: V_FOV,
+            samples: SAMPLE_NUM,
+            max_depth: REFLECT_DEPTH,
+            terminal_color: BLACK,
+            shadow_epsilon: None,
+            world_scale: 1.0,
+            focus_dist: None,
+            focus_point: None,
+            defocus_angle: DEFOCUS_ANGLE,
+            lens: None,
+            sensor_height: SENSOR_HEIGHT,
+            aperture: Aperture::Circle,
+            shutter: (0.0, 0.0),
+            ppm_format: PpmFormat::Ascii,
+            output_format: None,
+            threads: None,
+            progress: None,
+            quiet: false,
+            cancel: None,
+            seed: None,
+            vup: Vec3::new([0.0, 1.0, 0.0]),
+            projection: Projection::Perspective,
+            sampling: SamplingStrategy::Random,
+            filter: PixelFilter::Box,
+            supersample: 1,
+            adaptive: None,
+            russian_roulette: None,
+            clamp_indirect: None,
+            path_regularization: 0.0,
+            background: Background::default(),
+            transparent_background: false,
+            skip_empty_tiles: false,
+            preview_stride: 1,
+            lights: Vec::new(),
+            output: OutputSettings::default(),
+            checkpoint: None,
+            resume: None,
+            time_budget: None,
+            #[cfg(feature = "denoise")]
+            denoiser: None,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn new() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    pub fn look_from(mut self, look_from: Point) -> CameraBuilder {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Point) -> CameraBuilder {
+        self.look_at = look_at;
+        self
+    }
+
+    /// Camera "up" direction; tilt it to roll the camera.
+    pub fn up(mut self, vup: Vec3) -> CameraBuilder {
+        self.vup = vup;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> CameraBuilder {
+        self.projection = projection;
+        self
+    }
+
+    /// Image width in pixels; unless `height` is set, the height follows
+    /// from the aspect ratio.
+    pub fn width(mut self, width: u32) -> CameraBuilder {
+        self.width = width;
+        self
+    }
+
+    /// Image height in pixels. Takes precedence over `aspect_ratio`: the
+    /// view is framed for exactly `width / height`.
+    pub fn height(mut self, height: u32) -> CameraBuilder {
+        self.height = Some(height);
+        self
+    }
+
+    /// Sets both dimensions in pixels, e.g. `size(1080, 1080)` for a square.
+    pub fn size(self, width: u32, height: u32) -> CameraBuilder {
+        self.width(width).height(height)
+    }
+
+    /// Width over height, used to derive the height when it is not set.
+    pub fn aspect_ratio(mut self, aspect_ratio: f64) -> CameraBuilder {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn v_fov(mut self, v_fov: f64) -> CameraBuilder {
+        self.v_fov = v_fov;
+        self
+    }
+
+    /// Samples per pixel.
+    pub fn samples(mut self, samples: u16) -> CameraBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn sampling(mut self, sampling: SamplingStrategy) -> CameraBuilder {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn pixel_filter(mut self, filter: PixelFilter) -> CameraBuilder {
+        self.filter = filter;
+        self
+    }
+
+    /// Renders `factor` x `factor` pixels for every output pixel and
+    /// averages them down, which smooths geometric edges without raising
+    /// `samples`. Render time and the memory of the internal buffers grow
+    /// by `factor^2`; the written image keeps the requested size.
+    pub fn supersample(mut self, factor: usize) -> CameraBuilder {
+        self.supersample = factor;
+        self
+    }
+
+    /// Samples each pixel in batches and stops once the standard error of
+    /// its mean luminance drops to `threshold`, taking between `min_samples`
+    /// and `max_samples` samples. Overrides `samples`.
+    pub fn adaptive(mut self, threshold: f64, min_samples: u16, max_samples: u16) -> CameraBuilder {
+        let min_samples = min_samples.max(2);
+        self.adaptive = Some(Adaptive {
+            threshold,
+            min_samples,
+            max_samples: max_samples.max(min_samples),
+        });
+        self
+    }
+
+    /// Before sampling a tile, traces one ray through each of its corners;
+    /// if all four miss the scene, the tile is filled with the background
+    /// (one lookup per pixel) instead. A big speedup for a small object
+    /// against a large empty background, but geometry thinner than a tile
+    /// that slips between the corners is lost. Only applies to tiled
+    /// renders, not to `render_progressive` or checkpointed renders.
+    pub fn skip_empty_tiles(mut self, skip: bool) -> CameraBuilder {
+        self.skip_empty_tiles = skip;
+        self
+    }
+
+    /// Traces only every `n`th row and repeats each one over the rows
+    /// below it, for a quick look at the composition in about `1/n` of
+    /// the time. The image keeps its full size and framing. Like
+    /// `skip_empty_tiles`, only applies to tiled renders.
+    pub fn preview_stride(mut self, n: usize) -> CameraBuilder {
+        self.preview_stride = n.max(1);
+        self
+    }
+
+    /// Maximum number of bounces per path: 0 only sees lights and the
+    /// background directly, 1 adds direct lighting, and so on. A path that
+    /// would scatter past the limit gathers the `terminal_color` instead.
+    pub fn max_depth(mut self, max_depth: u8) -> CameraBuilder {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Radiance assumed to arrive at the last bounce allowed by
+    /// `max_depth`; black by default. A rough average of the scene's
+    /// brightness, e.g. the sky color, keeps paths trapped between
+    /// mirrors or inside glass from darkening when they are cut off.
+    pub fn terminal_color(mut self, color: Color) -> CameraBuilder {
+        self.terminal_color = color;
+        self
+    }
+
+    /// How far a ray must travel before it can hit anything, 0.001 meters
+    /// (see `world_scale`) by default. Rays leaving a surface would
+    /// otherwise hit it again at rounding distance and darken it with
+    /// speckles (shadow acne). Too large an offset lets rays skip past
+    /// nearby geometry instead, so contact shadows come loose.
+    pub fn shadow_epsilon(mut self, epsilon: f64) -> CameraBuilder {
+        self.shadow_epsilon = Some(epsilon.max(0.0));
+        self
+    }
+
+    /// Scene units per meter, 1 by default: 1000 for a scene modeled in
+    /// millimeters, 100 for centimeters. The camera's lengths that are
+    /// not given in scene units follow it, namely the default
+    /// `focus_dist` and `shadow_epsilon` and the aperture of `lens`, so
+    /// a scene frames, blurs and shades the same whatever its units.
+    /// Lengths set explicitly are taken as scene units and left alone.
+    pub fn world_scale(mut self, units_per_meter: f64) -> CameraBuilder {
+        if units_per_meter > 0.0 {
+            self.world_scale = units_per_meter;
+        }
+        self
+    }
+
+    /// Lets paths that have bounced `start_depth` times be terminated at
+    /// random in proportion to how little light they can still carry.
+    pub fn russian_roulette(mut self, enabled: bool, start_depth: u8) -> CameraBuilder {
+        self.russian_roulette = enabled.then_some(start_depth);
+        self
+    }
+
+    /// Limits the luminance each sample gathers after its first bounce to
+    /// `max_luminance`, scaling the color down without changing its hue.
+    /// This removes fireflies from rare bright paths (caustics through
+    /// glass, say) at the cost of some energy; lights and background seen
+    /// directly are not clamped.
+    pub fn clamp_indirect(mut self, max_luminance: f64) -> CameraBuilder {
+        self.clamp_indirect = Some(max_luminance.max(0.0));
+        self
+    }
+
+    /// Roughens mirror and glass bounces once a path has scattered off a
+    /// diffuse surface, spreading each over a cone whose half-angle is
+    /// `roughness` (0 to 1) times a right angle. Those bounces then sample
+    /// `lights` too, so light reaching diffuse surfaces through glass or
+    /// mirrors (caustics) is found on purpose rather than by a few lucky
+    /// paths. This tames fireflies in glass-heavy scenes at the cost of
+    /// slightly blurred caustics. Specular surfaces seen directly, or
+    /// through other specular ones, stay sharp. Around 0.1 to 0.3 is a
+    /// good start.
+    pub fn path_regularization(mut self, roughness: f64) -> CameraBuilder {
+        self.path_regularization = roughness.max(0.0);
+        self
+    }
+
+    /// What rays that miss all geometry see.
+    pub fn background(mut self, background: Background) -> CameraBuilder {
+        self.background = background;
+        self
+    }
+
+    /// Makes the background transparent for compositing: camera rays that
+    /// miss all geometry get an alpha of 0 instead of the background color,
+    /// which still lights the scene. The image gets an alpha channel and
+    /// PNGs are written as RGBA. Renders with checkpoints keep no coverage
+    /// and come out over black.
+    pub fn transparent_background(mut self, transparent: bool) -> CameraBuilder {
+        self.transparent_background = transparent;
+        self
+    }
+
+    /// Emitters to sample directly: every diffuse bounce also sends a
+    /// shadow ray towards one of them, which greatly reduces noise from
+    /// small lights. Each light must also be part of the rendered world,
+    /// and should implement `Hittable::pdf_value` and `random`.
+    pub fn lights(mut self, lights: Vec<Arc<dyn Hittable>>) -> CameraBuilder {
+        self.lights = lights;
+        self
+    }
+
+    /// Distance from `look_from` to the plane in perfect focus, 10 meters
+    /// (see `world_scale`) by default. Replaces an earlier `focus_on`.
+    pub fn focus_dist(mut self, focus_dist: f64) -> CameraBuilder {
+        self.focus_dist = Some(focus_dist);
+        self.focus_point = None;
+        self
+    }
+
+    /// Focuses on `point`: the focus distance becomes its depth along the
+    /// view direction, so the focal plane passes through it wherever it is
+    /// in the frame. Replaces an earlier `focus_dist`; a point behind the
+    /// camera is ignored.
+    pub fn focus_on(mut self, point: Point) -> CameraBuilder {
+        self.focus_point = Some(point);
+        self
+    }
+
+    /// Cone angle in degrees of the rays through each pixel; 0 disables defocus blur.
+    pub fn defocus_angle(mut self, defocus_angle: f64) -> CameraBuilder {
+        self.defocus_angle = defocus_angle;
+        self
+    }
+
+    /// Frames and blurs like a real lens, e.g. `lens(50.0, 1.8)` for a
+    /// 50mm f/1.8: the field of view follows from the focal length (in
+    /// millimeters) and `sensor_height`, the aperture diameter is the focal
+    /// length over the f-number, and scene units are taken to be meters
+    /// unless `world_scale` says otherwise.
+    /// An infinite f-number gives a pinhole. Overrides `v_fov` and
+    /// `defocus_angle`.
+    pub fn lens(mut self, focal_length: f64, f_number: f64) -> CameraBuilder {
+        self.lens = Some(Lens { focal_length, f_number });
+        self
+    }
+
+    /// Sensor height in millimeters for `lens`; 24 (full frame) by default.
+    pub fn sensor_height(mut self, sensor_height: f64) -> CameraBuilder {
+        self.sensor_height = sensor_height;
+        self
+    }
+
+    /// Shape of the defocus blur; `Circle` by default.
+    pub fn aperture(mut self, aperture: Aperture) -> CameraBuilder {
+        self.aperture = aperture;
+        self
+    }
+
+    /// Shutter interval: every primary ray gets a random time in
+    /// `[open, close]`, so moving objects blur. Closed (0, 0) by default.
+    pub fn shutter(mut self, open: f64, close: f64) -> CameraBuilder {
+        self.shutter = (open.min(close), open.max(close));
+        self
+    }
+
+    /// Encoding used when `render` writes a PPM file.
+    pub fn ppm_format(mut self, ppm_format: PpmFormat) -> CameraBuilder {
+        self.ppm_format = ppm_format;
+        self
+    }
+
+    /// Format `render` writes regardless of the file extension. By default
+    /// it follows the extension: `.png`, `.hdr`, otherwise PPM.
+    pub fn output_format(mut self, output_format: OutputFormat) -> CameraBuilder {
+        self.output_format = Some(output_format);
+        self
+    }
+
+    /// Display gamma applied when writing 8-bit images; 1.0 keeps values linear.
+    pub fn gamma(mut self, gamma: f64) -> CameraBuilder {
+        self.output.gamma = gamma;
+        self
+    }
+
+    /// Multiplies the linear image before tone mapping; 2.0 doubles the brightness.
+    pub fn exposure(mut self, exposure: f64) -> CameraBuilder {
+        self.output.exposure = exposure;
+        self
+    }
+
+    /// Curve used to roll off highlights before gamma encoding.
+    pub fn tone_map(mut self, tone_map: ToneMap) -> CameraBuilder {
+        self.output.tone_map = tone_map;
+        self
+    }
+
+    /// Applies every setting of `config`, replacing whatever was set
+    /// before; later calls can still override single settings.
+    pub fn config(mut self, config: RenderConfig) -> CameraBuilder {
+        self.width = config.width;
+        self.height = config.height;
+        self.samples = config.samples;
+        self.max_depth = config.max_depth;
+        self.threads = config.threads;
+        self.output.tone_map = config.tone_map;
+        self.output.exposure = config.exposure;
+        self.background = config.background;
+        self.seed = config.seed;
+        self
+    }
+
+    /// Number of worker threads; defaults to the available parallelism.
+    pub fn threads(mut self, threads: usize) -> CameraBuilder {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Reports progress through `progress` instead of printing to stderr.
+    /// It is called a few times per second from a helper thread, and once
+    /// more with 1.0 when the render completes.
+    pub fn progress(mut self, progress: impl Fn(f64) + Send + Sync + 'static) -> CameraBuilder {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Silences the progress line, timing and completion messages that
+    /// are otherwise printed to stderr. Errors are still returned, and a
+    /// `progress` callback is still called.
+    pub fn quiet(mut self, quiet: bool) -> CameraBuilder {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Stops the render soon after `cancel` is set to true: workers check it
+    /// before every tile and the finished pixels are still returned or written.
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> CameraBuilder {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Makes the render reproducible: with a fixed seed the output is
+    /// identical across runs, regardless of the thread count.
+    pub fn seed(mut self, seed: u64) -> CameraBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Renders full frames in passes of one sample per pixel, like
+    /// `render_progressive`, and saves the accumulated samples to `path`
+    /// whenever `interval` has passed since the last save, after the last
+    /// pass and on cancellation. Continue such a render with `resume_from`.
+    /// Adaptive sampling and denoising do not apply to these renders.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, interval: Duration) -> CameraBuilder {
+        self.checkpoint = Some((path.into(), interval));
+        self
+    }
+
+    /// Starts pass-by-pass renders from the samples saved in a checkpoint,
+    /// adding passes until there are `samples` in total. The camera must
+    /// render at the checkpoint's size; with the same seed the result
+    /// matches an uninterrupted render exactly.
+    pub fn resume_from(mut self, path: impl Into<PathBuf>) -> CameraBuilder {
+        self.resume = Some(path.into());
+        self
+    }
+
+    /// Renders for `budget` instead of a fixed number of samples: full
+    /// frames in passes of one sample per pixel, like `render_progressive`,
+    /// until the time is up, then stops mid-pass and divides every pixel
+    /// by the samples it got. The first pass always completes, so even a
+    /// tiny budget gives every pixel a sample. With a `checkpoint`, the
+    /// render runs on to the end of the pass instead, as checkpoints only
+    /// hold whole passes. Adaptive sampling and denoising do not apply.
+    pub fn time_budget(mut self, budget: Duration) -> CameraBuilder {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Runs every full-frame render through `denoiser`, which makes low
+    /// sample counts usable at the cost of some blur.
+    #[cfg(feature = "denoise")]
+    pub fn denoise(mut self, denoiser: Denoiser) -> CameraBuilder {
+        self.denoiser = Some(denoiser);
+        self
+    }
+
+    pub fn build(&self) -> Camera {
+        let width = self.width.max(1) as f64;
+        let height = match (self.projection, self.height) {
+            (Projection::Equirectangular, _) => (width / 2.0).max(1.0).floor(),
+            (_, Some(height)) => height.max(1) as f64,
+            (_, None) => (width / self.aspect_ratio).max(1.0).floor(),
+        };
+        // the rounded pixel grid decides the frame, so pixels stay square
+        let aspect_ratio = width / height;
+        let factor = self.supersample.max(1) as f64;
+        let (width, height) = (width * factor, height * factor);
+
+        let forward = (self.look_at - self.look_from).unit();
+        let focus_dist = self.focus_point
+            .map(|point| (point - self.look_from).dot(&forward))
+            .filter(|depth| *depth > 0.0)
+            .or(self.focus_dist)
+            .unwrap_or(FOCUS_DIST * self.world_scale);
+        let (v_fov, defocus_angle) = match self.lens {
+            Some(lens) => lens.angles(self.sensor_height, focus_dist, self.world_scale),
+            None => (self.v_fov, self.defocus_angle),
+        };
+        let (viewport_height, viewport_dist) = match self.projection {
+            // the viewport is unused; keep it well defined
+            Projection::Perspective | Projection::Equirectangular => {
+                let theta = v_fov.to_radians();
+                let h = (theta / 2.0).tan();
+                (2.0 * h * focus_dist, focus_dist)
+            },
+            // the viewport sits on the eye plane; rays start on it
+            Projection::Orthographic(height) => (height, 0.0),
+        };
+        let viewport_width = viewport_height * aspect_ratio;
+        let defocus_radius = focus_dist * (defocus_angle / 2.0).to_radians().tan();
+
+        let mut camera = Camera {
+            eye: self.look_from,
+            projection: self.projection,
+            vup: self.vup,
+            forward: Vec3::new([0.0, 0.0, -1.0]),
+            right: Vec3::new([1.0, 0.0, 0.0]),
+            up: Vec3::new([0.0, 1.0, 0.0]),
+            width,
+            height,
+            viewport_width,
+            viewport_height,
+            viewport_dist,
+            defocus_radius,
+            pixel_start: self.look_from,
+            delta_u: Vec3::new([0.0; 3]),
+            delta_v: Vec3::new([0.0; 3]),
+            sample_num: self.samples,
+            sampling: self.sampling,
+            filter: self.filter,
+            supersample: self.supersample.max(1),
+            strata: (self.adaptive.map_or(self.samples, |a| a.min_samples) as f64).sqrt() as u16,
+            adaptive: self.adaptive,
+            trace: TraceSettings {
+                max_depth: self.max_depth,
+                terminal_color: self.terminal_color,
+                shadow_epsilon: self.shadow_epsilon.unwrap_or(RAY_EPSILON * self.world_scale),
+                russian_roulette: self.russian_roulette,
+                clamp_indirect: self.clamp_indirect,
+                path_regularization: self.path_regularization,
+                background: self.background.clone(),
+                lights: self.lights.clone(),
+            },
+            defocus_angle,
+            aperture: self.aperture,
+            shutter: self.shutter,
+            disk_u: Vec3::new([0.0; 3]),
+            disk_v: Vec3::new([0.0; 3]),
+            ppm_format: self.ppm_format,
+            output_format: self.output_format,
+            output: self.output,
+            threads: self.threads.unwrap_or_else(default_threads).max(1),
+            progress: self.progress.clone(),
+            quiet: self.quiet,
+            cancel: self.cancel.clone(),
+            seed: self.seed,
+            checkpoint: self.checkpoint.clone(),
+            resume: self.resume.clone(),
+            time_budget: self.time_budget,
+            #[cfg(feature = "denoise")]
+            denoiser: self.denoiser,
+            transparent_background: self.transparent_background,
+            skip_empty_tiles: self.skip_empty_tiles,
+            preview_stride: self.preview_stride,
+        };
+        camera.set_view(self.look_from, self.look_at);
+        camera
+    }
 }
 
 impl Camera {
     pub fn new(look_from: Point, look_at: Point) -> Camera {
-        let width = WIDTH;
-        let height = (width / ASPECT_RATIO).max(1.0).floor();
-
-        let focus_dist = FOCUS_DIST;
-        let defocus_angle = DEFOCUS_ANGLE;
-        let theta = V_FOV.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * ASPECT_RATIO;
-        
-        let vup = Vec3::new([0.0, 1.0, 0.0]);
+        CameraBuilder::new()
+            .look_from(look_from)
+            .look_at(look_at)
+            .build()
+    }
+
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::new()
+    }
+
+    /// A builder starting from `config` rather than the defaults.
+    pub fn from_config(config: RenderConfig) -> CameraBuilder {
+        CameraBuilder::new().config(config)
+    }
+
+    /// The same camera moved to `look_from` and aimed at `look_at`, e.g.
+    /// for the frames of a turntable. Only the orientation is recomputed:
+    /// the field of view, resolution, focus distance and aperture stay as
+    /// built, which is much cheaper than building a new camera.
+    pub fn with_view(mut self, look_from: Point, look_at: Point) -> Camera {
+        self.set_view(look_from, look_at);
+        self
+    }
+
+    /// Derives the basis, viewport corner and pixel steps from the eye
+    /// position and target, keeping the viewport size.
+    fn set_view(&mut self, look_from: Point, look_at: Point) {
         let w = (look_from - look_at).unit();
-        let u = vup.cross(&w).unit();
+        let u = stable_up(self.vup, w).cross(&w).unit();
         let v = w.cross(&u);
-        
-        let viewport_u = viewport_width * u;
-        let viewport_v = viewport_height * v.reverse();
-
-        let delta_u = viewport_u / width;
-        let delta_v = viewport_v / height;
-        let viewport_upper_left = look_from - focus_dist * w - viewport_u / 2.0 - viewport_v / 2.0;
-        let start = viewport_upper_left + (delta_u + delta_v) / 2.0;
-        
-        let defocus_radius = focus_dist * f64::from(defocus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
-
-        Camera {
-            eye: look_from,
-            width: width,
-            height: height,
-            pixel_start: start,
-            delta_u: delta_u,
-            delta_v: delta_v,
-            sample_num: SAMPLE_NUM,
-            reflect_depth: REFLECT_DEPTH,
-            defocus_angle: defocus_angle,
-            disk_u: defocus_disk_u,
-            disk_v: defocus_disk_v,
-        }
-    }
-
-    pub fn render(&self, environment: Arc<impl Hittable + Sync + Send + 'static>) {
-        let now = std::time::Instant::now();
-        let mut photo = match File::create("out.ppm") {
-            Err(e) => panic!("Could not create photo: {}", e),
-            Ok(file) => BufWriter::new(file),
-        };
-        let header = format!("P3\n{} {}\n255\n", self.width, self.height);
-        let _ = photo.write_all(header.as_bytes());
-
-        let height = self.height as i64;
-        let width = self.width as i64;
-
-        let counter = Arc::new(AtomicUsize::new(0));
-        let total = (width * height) as usize;
-        // pixel buffer
-        let pixels = Arc::new(Mutex::new(vec![BLACK; total]));
-
-        let num_threads = THREADS_NUM;
-        let chunk_size = height / num_threads;
-        let mut handles = vec![];
-
-        for thread_id in 0..num_threads {
-            let environment = Arc::clone(&environment);
-            let pixels = Arc::clone(&pixels);
-            let eye = self.eye.clone();
-            let pixel_start = self.pixel_start.clone();
-            let delta_u = self.delta_u.clone();
-            let delta_v = self.delta_v.clone();
-            let sample_num = self.sample_num;
-            let reflect_depth = self.reflect_depth;
-            let defocus_angle = self.defocus_angle;
-            let disk_u = self.disk_u.clone();
-            let disk_v = self.disk_v.clone();
-            let counter = Arc::clone(&counter);
-
-            let handle = thread::spawn(move || {
-                let start_row = thread_id * chunk_size;
-                let end_row = if thread_id == num_threads - 1 {
-                    height
-                } else {
-                    (thread_id + 1) * chunk_size
+
+        let viewport_u = self.viewport_width * u;
+        let viewport_v = self.viewport_height * -v;
+
+        self.delta_u = viewport_u / self.width;
+        self.delta_v = viewport_v / self.height;
+        let viewport_upper_left = look_from - self.viewport_dist * w - viewport_u / 2.0 - viewport_v / 2.0;
+        self.pixel_start = viewport_upper_left + (self.delta_u + self.delta_v) / 2.0;
+
+        self.eye = look_from;
+        self.forward = -w;
+        self.right = u;
+        self.up = v;
+        self.disk_u = u * self.defocus_radius;
+        self.disk_v = v * self.defocus_radius;
+    }
+
+    /// Settings used to encode the linear render buffer for display.
+    pub fn output_settings(&self) -> &OutputSettings {
+        &self.output
+    }
+
+    /// Renders `scene` to `output_path`. A `.png` path is written directly as PNG
+    /// and a `.hdr` path as linear Radiance HDR; anything else is written
+    /// as PPM, plus a PNG copy next to it when the `png` feature is
+    /// enabled. `CameraBuilder::output_format` overrides the extension.
+    /// Without the `png` feature, PNG output is a `Write` error of kind
+    /// `Unsupported`, raised before rendering. A cancelled render still
+    /// writes the pixels it finished.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        output_path: impl AsRef<Path>,
+    ) -> Result<RenderOutcome, RenderError> {
+        let output_path = output_path.as_ref();
+        self.output_format_for(output_path)?;
+        // create the file up front so a bad path fails before rendering
+        let photo = BufWriter::new(File::create(output_path).map_err(RenderError::Create)?);
+        let (image, outcome) = self.render_image(scene)?;
+        self.write_image(&image, output_path, photo)?;
+        match outcome {
+            _ if self.quiet => {},
+            RenderOutcome::Completed => eprintln!("Completed!"),
+            RenderOutcome::Cancelled => eprintln!("Cancelled, wrote the finished pixels"),
+        }
+        Ok(outcome)
+    }
+
+    /// Renders `scene` and writes it to `out` in `format`, e.g. to stdout
+    /// so the image can be piped into another program. Unlike `render`,
+    /// the camera's output format is not consulted and no PNG copy is made.
+    pub fn render_to_writer(
+        &self,
+        scene: &Scene,
+        mut out: impl Write,
+        format: OutputFormat,
+    ) -> Result<RenderOutcome, RenderError> {
+        let (image, outcome) = self.render_image(scene)?;
+        image.write(&mut out, format, &self.output).map_err(RenderError::Write)?;
+        out.flush().map_err(RenderError::Write)?;
+        Ok(outcome)
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+    }
+
+    /// Writes `image` to the already created `photo` at `output_path`,
+    /// following the format rules of `render`.
+    pub(crate) fn write_image(&self, image: &Image, output_path: &Path, mut photo: BufWriter<File>) -> Result<(), RenderError> {
+        let format = self.output_format_for(output_path)?;
+        image.write(&mut photo, format, &self.output).map_err(RenderError::Write)?;
+        photo.flush().map_err(RenderError::Write)?;
+        drop(photo);
+
+        if matches!(format, OutputFormat::Ppm(_)) && cfg!(feature = "png") {
+            let png_path = output_path.with_extension("png");
+            let mut png_file = BufWriter::new(File::create(png_path).map_err(RenderError::Create)?);
+            image.write(&mut png_file, OutputFormat::Png, &self.output).map_err(RenderError::Write)?;
+            png_file.flush().map_err(RenderError::Write)?;
+        }
+        Ok(())
+    }
+
+    /// The format `render` uses for `output_path`; an error for PNG
+    /// without the `png` feature, rather than PPM bytes in a `.png` file.
+    pub(crate) fn output_format_for(&self, output_path: &Path) -> Result<OutputFormat, RenderError> {
+        let format = self.output_format.unwrap_or_else(|| OutputFormat::from_path(output_path, self.ppm_format));
+        if matches!(format, OutputFormat::Png) && !cfg!(feature = "png") {
+            let error = io::Error::new(io::ErrorKind::Unsupported, "PNG support is disabled, enable the `png` feature");
+            return Err(RenderError::Write(error));
+        }
+        Ok(format)
+    }
+
+    /// Renders the scene into memory without touching the filesystem.
+    /// If the render is cancelled, unfinished pixels are black with a
+    /// sample count of 0.
+    pub fn render_to_buffer(&self, scene: &Scene) -> Result<Image, RenderError> {
+        self.render_image(scene).map(|(image, _)| image)
+    }
+
+    /// `render_to_buffer`, also telling whether cancellation cut it short.
+    fn render_image(&self, scene: &Scene) -> Result<(Image, RenderOutcome), RenderError> {
+        if self.checkpoint.is_some() || self.resume.is_some() || self.time_budget.is_some() {
+            return self.render_passes(scene);
+        }
+        #[cfg(feature = "denoise")]
+        if let Some(denoiser) = &self.denoiser {
+            return self.render_output(scene).map(|(aovs, outcome)| {
+                let mut denoised = denoiser.denoise(&aovs);
+                if let Some(stats) = aovs.color.stats() {
+                    denoised = denoised.with_stats(*stats);
+                }
+                let denoised = match aovs.color.alpha() {
+                    Some(alpha) => denoised.with_alpha(alpha.to_vec()),
+                    None => denoised,
                 };
+                (denoised, outcome)
+            });
+        }
+        self.render_rect(scene, 0, 0, self.image_width(), self.image_height(), false)
+            .map(|(image, _, outcome)| (self.downsample(image), outcome))
+    }
 
-                let mut local_pixels = vec![BLACK; ((end_row - start_row) * width) as usize];
-
-                for i in start_row..end_row {
-                    let y = i as f64;
-                
-                    for j in 0..width {
-                        let x = j as f64;
-                        let mut color = BLACK;
-
-                        for _ in 0..sample_num {
-                            let offset = Vec3::random(-0.5, 0.5);
-                            let sample_pixel = pixel_start
-                                + (y + offset.y()) * delta_v
-                                + (x + offset.x()) * delta_u;
-                    
-                            let ray_org = if defocus_angle <= 0.0 {
-                                eye
-                            } else {
-                                defocus_sample(eye, disk_u, disk_v)
-                            };
-                            let ray = Ray::new(ray_org, sample_pixel - ray_org);
-                            color = color + ray_color(&ray, &*environment, reflect_depth);
-                        }
-                
-                        let samples_average_color = color / sample_num as f64;
-                        local_pixels[((i - start_row) * width + j) as usize] = samples_average_color;
+    /// Renders the beauty image together with auxiliary buffers for
+    /// compositing and denoising. They are averaged over the same samples
+    /// as the colors, so edges are anti-aliased alike.
+    pub fn render_aovs(&self, scene: &Scene) -> Result<RenderOutput, RenderError> {
+        self.render_output(scene).map(|(output, _)| output)
+    }
 
-                        counter.fetch_add(1, Ordering::SeqCst);
+    fn render_output(&self, scene: &Scene) -> Result<(RenderOutput, RenderOutcome), RenderError> {
+        let (width, height) = (self.image_width(), self.image_height());
+        let (color, hits, outcome) = self.render_rect(scene, 0, 0, width, height, true)?;
+        let hits = hits.unwrap_or_default();
+        let channel = |f: &dyn Fn(&FirstHit) -> Color| {
+            self.downsample(Image::new(width, height, hits.iter().map(f).collect()))
+        };
+        let output = RenderOutput {
+            color: self.downsample(color),
+            depth: channel(&|hit| Color::new([hit.distance; 3])),
+            // encoded per sample, so misses stay 0 and edge pixels blend toward it
+            normal: channel(&|hit| 0.5 * hit.normal + 0.5 * hit.coverage),
+            albedo: channel(&|hit| hit.albedo),
+        };
+        Ok((output, outcome))
+    }
+
+    /// Renders only the pixels in columns `x0..x1` and rows `y0..y1` of the
+    /// full image, row by row. The view is still framed for the whole image,
+    /// so regions rendered separately (even on different machines) stitch
+    /// into exactly the full render; with a seed set they match it bit for bit.
+    /// The rectangle is clipped to the image.
+    pub fn render_region(
+        &self,
+        scene: &Scene,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) -> Result<Vec<Color>, RenderError> {
+        let factor = self.supersample;
+        let x1 = x1.min(self.image_width() / factor);
+        let y1 = y1.min(self.image_height() / factor);
+        if x0 >= x1 || y0 >= y1 {
+            return Ok(Vec::new());
+        }
+        self.render_rect(scene, x0 * factor, y0 * factor, x1 * factor, y1 * factor, false)
+            .map(|(image, _, _)| self.downsample(image).into_pixels())
+    }
+
+    /// Renders in passes of one sample per pixel across the whole image,
+    /// so a noisy but complete picture is available at once and sharpens
+    /// over time. After each pass `on_pass` gets the running average (row
+    /// major, top-left first) and the number of passes so far. Runs as many
+    /// passes as the camera has samples, or until its `time_budget` is
+    /// spent; adaptive sampling does not apply.
+    /// The cancel flag is checked between passes. Checkpoints are saved
+    /// and resumed from as configured on the builder.
+    pub fn render_progressive(
+        &self,
+        scene: &Scene,
+        mut on_pass: impl FnMut(&[Color], usize),
+    ) -> Result<RenderOutcome, RenderError> {
+        self.accumulate(scene, &StatsCollector::default(), |accumulation| {
+            let image = Image::new(accumulation.width, accumulation.height, accumulation.average());
+            on_pass(self.downsample(image).pixels(), accumulation.passes as usize)
+        }).map(|(_, outcome)| outcome)
+    }
+
+    /// Full-frame render through `accumulate`, for checkpointing and
+    /// time budgets.
+    fn render_passes(&self, scene: &Scene) -> Result<(Image, RenderOutcome), RenderError> {
+        let start = Instant::now();
+        let stats = StatsCollector::default();
+        let (accumulation, outcome) = self.accumulate(scene, &stats, |accumulation| {
+            let fraction = match self.time_budget {
+                Some(budget) => (start.elapsed().as_secs_f64() / budget.as_secs_f64()).min(1.0),
+                None => accumulation.passes as f64 / self.sample_num.max(1) as f64,
+            };
+            match (&self.progress, self.time_budget) {
+                (Some(progress), _) => progress(fraction),
+                _ if self.quiet => {},
+                (None, Some(budget)) => {
+                    let left = budget.saturating_sub(start.elapsed());
+                    // trailing spaces wipe leftovers of a longer previous line
+                    eprint!("\rPass {}, {:.1?} left   ", accumulation.passes, left);
+                },
+                (None, None) => eprint!("\rPass {} of {}", accumulation.passes, self.sample_num),
+            }
+        })?;
+        let stats = stats.finish(start.elapsed());
+        if !self.quiet && self.progress.is_none() {
+            eprintln!("\nFinished in {:.2?}\n{}", start.elapsed(), stats);
+        }
+        let image = Image::new(accumulation.width, accumulation.height, accumulation.average());
+        Ok((self.downsample(image.with_stats(stats)), outcome))
+    }
+
+    /// Adds passes of one sample per pixel to the accumulation, starting
+    /// from the resume checkpoint if there is one, until the camera's
+    /// sample count is reached, its time budget runs out or the render is
+    /// cancelled. Saves checkpoints as configured and calls `on_pass` after
+    /// every pass, and after one cut short by the budget. What the passes
+    /// do is added to `stats`.
+    fn accumulate(
+        &self,
+        scene: &Scene,
+        stats: &StatsCollector,
+        mut on_pass: impl FnMut(&Accumulation),
+    ) -> Result<(Accumulation, RenderOutcome), RenderError> {
+        let (width, height) = (self.image_width(), self.image_height());
+        let mut accumulation = match &self.resume {
+            Some(path) => Accumulation::load(path, width, height).map_err(RenderError::Resume)?,
+            None => Accumulation::new(width, height),
+        };
+        let save = |accumulation: &Accumulation| match &self.checkpoint {
+            Some((path, _)) => accumulation.save(path).map_err(RenderError::Checkpoint),
+            None => Ok(()),
+        };
+        let mut last_save = Instant::now();
+
+        let total = self.pixel_count();
+        let band_len = (TILE_SIZE * width).max(1);
+        let num_threads = self.threads.min(total.div_ceil(band_len)).max(1);
+        #[cfg(feature = "rayon")]
+        let pool = thread_pool(num_threads);
+        let trace = self.trace_settings(scene);
+        let deadline = self.time_budget.map(|budget| Instant::now() + budget);
+        let out_of_time = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        // a budget replaces the sample count
+        let passes = if deadline.is_some() { u16::MAX } else { self.sample_num };
+
+        for pass in accumulation.passes..passes {
+            if self.is_cancelled() {
+                save(&accumulation)?;
+                return Ok((accumulation, RenderOutcome::Cancelled));
+            }
+            // every pixel gets at least one sample
+            if pass > 0 && out_of_time() {
+                break;
+            }
+            // checkpoints only hold whole passes
+            let may_stop = pass > 0 && self.checkpoint.is_none();
+
+            let render_band = |(band_index, band): (usize, &mut [(Color, f64)])| {
+                for (offset, sum) in band.iter_mut().enumerate() {
+                    let pixel = band_index * band_len + offset;
+                    if let Some(seed) = self.seed {
+                        let pass_seed = random::stream_seed(seed, pass as u64);
+                        random::reseed(random::stream_seed(pass_seed, pixel as u64));
                     }
+                    let (color, _, weight) = self.sample(pixel / width, pixel % width, pass, &**scene.world(), &trace);
+                    sum.0 += weight * color;
+                    sum.1 += weight;
                 }
+            };
+            #[cfg(not(feature = "rayon"))]
+            let bands = Mutex::new(accumulation.sums.chunks_mut(band_len).enumerate());
+            #[cfg(not(feature = "rayon"))]
+            let panicked = thread::scope(|scope| {
+                let mut handles = vec![];
+                for _ in 0..num_threads {
+                    handles.push(scope.spawn(|| loop {
+                        stats.flush();
+                        if may_stop && out_of_time() {
+                            break;
+                        }
+                        let next = bands.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        let Some(next) = next else { break };
+                        render_band(next);
+                    }));
+                }
+                handles.into_iter().fold(false, |panicked, handle| panicked | handle.join().is_err())
+            });
+            // set when a band is passed over because the budget ran out
+            #[cfg(feature = "rayon")]
+            let skipped = AtomicBool::new(false);
+            #[cfg(feature = "rayon")]
+            let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+                pool.install(|| accumulation.sums.par_chunks_mut(band_len).enumerate().for_each(|band| {
+                    if may_stop && out_of_time() {
+                        skipped.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    render_band(band);
+                    stats.flush();
+                }));
+            })).is_err();
+            if panicked {
+                return Err(RenderError::ThreadPanicked);
+            }
+            #[cfg(not(feature = "rayon"))]
+            let unfinished = bands.into_inner().unwrap_or_else(|e| e.into_inner()).next().is_some();
+            #[cfg(feature = "rayon")]
+            let unfinished = skipped.into_inner();
+            if unfinished {
+                // out of time mid-pass: the pixels it reached keep their sample
+                on_pass(&accumulation);
+                break;
+            }
 
-                let mut pixels = pixels.lock().unwrap();
-                for i in start_row..end_row {
-                    for j in 0..width {
-                        pixels[(i * width + j) as usize] = local_pixels[((i - start_row) * width + j) as usize];
+            accumulation.passes = pass + 1;
+            on_pass(&accumulation);
+            if self.checkpoint.as_ref().is_some_and(|(_, interval)| last_save.elapsed() >= *interval) {
+                save(&accumulation)?;
+                last_save = Instant::now();
+            }
+        }
+        save(&accumulation)?;
+        Ok((accumulation, RenderOutcome::Completed))
+    }
+
+    /// Renders a rectangle of the image, with the first-hit data of every
+    /// pixel if `with_aovs` is set, and whether cancellation cut it short.
+    fn render_rect(
+        &self,
+        scene: &Scene,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+        with_aovs: bool,
+    ) -> Result<(Image, Option<Vec<FirstHit>>, RenderOutcome), RenderError> {
+        // coverage comes from the first hits
+        let with_aovs = with_aovs || self.transparent_background;
+        let now = Instant::now();
+        // start on a traced row, so every skipped row has one above to copy
+        let lead = y0 - self.preview_source(y0);
+        let y0 = y0 - lead;
+        let height = y1 - y0;
+        let width = x1 - x0;
+
+        let counter = AtomicUsize::new(0);
+        let total = width * height;
+        let mut pixels = vec![BLACK; total];
+        let mut sample_counts = vec![0; total];
+        let mut hits = with_aovs.then(|| vec![FirstHit::MISS; total]);
+
+        // The buffer is split into bands of rows, each an exclusive slice
+        // handed to whichever worker pulls it next. Inside a band the pixels
+        // are visited tile by tile to keep rays coherent. With the rayon
+        // feature the bands are a fixed TILE_SIZE rows, and work stealing
+        // balances the end of the render instead.
+        let num_threads = self.threads.min(height).max(1);
+        let buffers = Band { y0, pixels: &mut pixels, counts: &mut sample_counts, hits: hits.as_deref_mut() };
+        #[cfg(not(feature = "rayon"))]
+        let bands = Mutex::new(BandQueue::new(buffers, width, num_threads));
+        #[cfg(feature = "rayon")]
+        let bands = buffers.chunks(width, TILE_SIZE).into_par_iter();
+        let done = AtomicBool::new(false);
+        let cut_short = AtomicBool::new(false);
+        let stats = StatsCollector::default();
+
+        let panicked = thread::scope(|scope| {
+            let mut handles = vec![];
+            #[cfg(not(feature = "rayon"))]
+            for _ in 0..num_threads {
+                let handle = scope.spawn(|| {
+                    stats.start_thread();
+                    loop {
+                        let next = bands.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        let Some(band) = next else { break };
+                        if !self.render_band(x0..x1, band, scene, &counter) {
+                            cut_short.store(true, Ordering::SeqCst);
+                        }
+                        stats.flush();
                     }
+                });
+                handles.push(handle);
+            }
+            // one thread drives a pool that splits the bands among its own workers
+            #[cfg(feature = "rayon")]
+            handles.push(scope.spawn(|| {
+                thread_pool(num_threads).install(|| bands.for_each(|band| {
+                    if !self.render_band(x0..x1, band, scene, &counter) {
+                        cut_short.store(true, Ordering::SeqCst);
+                    }
+                    stats.flush();
+                }));
+            }));
+
+            let progress = scope.spawn(|| {
+                let mut meter = ProgressMeter::new(now, total);
+                loop {
+                    let completed = counter.load(Ordering::SeqCst);
+                    let fraction = completed as f64 / total as f64;
+                    match &self.progress {
+                        Some(progress) => progress(fraction),
+                        None if self.quiet => {},
+                        None => {
+                            // trailing spaces wipe leftovers of a longer previous line
+                            eprint!("\r{}   ", meter.update(completed));
+                            let _ = std::io::stderr().flush();
+                        },
+                    }
+
+                    if completed >= total || done.load(Ordering::SeqCst) { break; }
+                    // woken early once the workers are done, so a short
+                    // render doesn't wait out the interval
+                    thread::park_timeout(PROGRESS_INTERVAL);
                 }
             });
-            handles.push(handle);
+
+            let mut panicked = false;
+            for handle in handles {
+                panicked |= handle.join().is_err();
+            }
+            // stop the progress loop early if a worker died or the render was cancelled
+            done.store(true, Ordering::SeqCst);
+            progress.thread().unpark();
+            panicked | progress.join().is_err()
+        });
+        if panicked {
+            return Err(RenderError::ThreadPanicked);
+        }
+
+        let stats = stats.finish(now.elapsed());
+        if !self.quiet {
+            eprintln!("\nRendering time: {}s\n{}", now.elapsed().as_secs(), stats);
+        }
+        self.fill_skipped_rows(&mut pixels, width);
+        self.fill_skipped_rows(&mut sample_counts, width);
+        pixels.drain(..lead * width);
+        sample_counts.drain(..lead * width);
+        if let Some(hits) = hits.as_mut() {
+            self.fill_skipped_rows(hits, width);
+            hits.drain(..lead * width);
         }
+        let height = height - lead;
+        let mut image = Image::new(width, height, pixels).with_sample_counts(sample_counts).with_stats(stats);
+        if let (true, Some(hits)) = (self.transparent_background, &hits) {
+            image = image.with_alpha(hits.iter().map(|hit| hit.coverage).collect());
+        }
+        let outcome = if cut_short.into_inner() { RenderOutcome::Cancelled } else { RenderOutcome::Completed };
+        Ok((image, hits, outcome))
+    }
+
+    pub(crate) fn image_width(&self) -> usize {
+        self.width as usize
+    }
+
+    pub(crate) fn image_height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub(crate) fn pixel_count(&self) -> usize {
+        self.image_width() * self.image_height()
+    }
 
-        let counter = Arc::clone(&counter);
-        let progress = thread::spawn(move || {
-            loop {
-                let completed = counter.load(Ordering::SeqCst);
-                let percentage = (completed as f64 / total as f64) * 100.0;
-                print!("\rProgress: {:.2}%", percentage);
-                std::io::stdout().flush().unwrap();
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub(crate) fn progress_callback(&self) -> Option<&ProgressCallback> {
+        self.progress.as_ref()
+    }
 
-                if completed >= total { break; }
-                thread::sleep(std::time::Duration::from_secs(1));
+    /// The row whose pixels row `i` of the internal grid repeats under
+    /// `preview_stride`; `i` itself if it is traced. Strides count output
+    /// rows, so all rows of a supersampled output row are traced together.
+    fn preview_source(&self, i: usize) -> usize {
+        let factor = self.supersample;
+        i - (i / factor) % self.preview_stride * factor
+    }
+
+    /// Copies every row skipped by `preview_stride` over from the row it
+    /// repeats. `buffer` holds rows of `width` pixels starting at a traced row.
+    pub(crate) fn fill_skipped_rows<T: Copy>(&self, buffer: &mut [T], width: usize) {
+        if self.preview_stride <= 1 || width == 0 {
+            return;
+        }
+        for i in 0..buffer.len() / width {
+            let source = self.preview_source(i);
+            if source != i {
+                buffer.copy_within(source * width..(source + 1) * width, i * width);
             }
-        });
+        }
+    }
+
+    /// Brings a render of the internal pixel grid down to the output size.
+    pub(crate) fn downsample(&self, image: Image) -> Image {
+        if self.supersample > 1 { image.downsample(self.supersample) } else { image }
+    }
 
-        for handle in handles {
-            handle.join().unwrap();
+    pub(crate) fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Settings for tracing `scene`: the camera's, with the scene's
+    /// background and lights where it sets them. Pass them to `ray_color`
+    /// to shade rays the way the camera would.
+    pub fn trace_settings(&self, scene: &Scene) -> TraceSettings {
+        let mut trace = self.trace.clone();
+        if let Some(background) = scene.background() {
+            trace.background = background.clone();
+        }
+        if let Some(lights) = scene.lights() {
+            trace.lights = lights.to_vec();
         }
-        progress.join().unwrap();
+        trace
+    }
 
-        println!("\nRendering time: {}s", now.elapsed().as_secs());
-        let pixels = pixels.lock().unwrap();
-        for color in pixels.iter() {
-            write_color(&mut photo, color);
+    /// Renders `band` over the columns in `columns`, adding finished pixels
+    /// to `counter`. The band is walked in TILE_SIZE wide tiles. Returns
+    /// false if the render was cancelled before the band was finished.
+    pub(crate) fn render_band(&self, columns: Range<usize>, band: Band, scene: &Scene, counter: &AtomicUsize) -> bool {
+        let environment = &**scene.world();
+        let trace = &self.trace_settings(scene);
+        let width = columns.len();
+        let rows = band.pixels.len() / width.max(1);
+        let Band { y0, pixels, counts, mut hits } = band;
+        for x0 in columns.clone().step_by(TILE_SIZE) {
+            if self.is_cancelled() {
+                return false;
+            }
+            let x1 = (x0 + TILE_SIZE).min(columns.end);
+            if self.skip_empty_tiles && self.tile_is_empty(x0..x1, y0..y0 + rows, environment) {
+                for row in 0..rows {
+                    for j in x0..x1 {
+                        let index = row * width + j - columns.start;
+                        pixels[index] = self.background_color(y0 + row, j, trace);
+                        counts[index] = 1;
+                        if let Some(hits) = hits.as_deref_mut() {
+                            hits[index] = FirstHit::MISS;
+                        }
+                    }
+                }
+                counter.fetch_add((x1 - x0) * rows, Ordering::SeqCst);
+                continue;
+            }
+            for row in 0..rows {
+                if self.preview_source(y0 + row) != y0 + row {
+                    // filled in from the traced row once the render is done
+                    counter.fetch_add(x1 - x0, Ordering::SeqCst);
+                    continue;
+                }
+                for j in x0..x1 {
+                    let i = y0 + row;
+                    // seeding per pixel rather than per thread keeps the random
+                    // sequence independent of scheduling and of the region
+                    if let Some(seed) = self.seed {
+                        let pixel = i * self.image_width() + j;
+                        random::reseed(random::stream_seed(seed, pixel as u64));
+                    }
+                    let (color, samples, hit) = self.pixel_color(i, j, environment, trace);
+                    let index = row * width + j - columns.start;
+                    pixels[index] = color;
+                    counts[index] = samples;
+                    if let Some(hits) = hits.as_deref_mut() {
+                        hits[index] = hit;
+                    }
+                }
+                counter.fetch_add(x1 - x0, Ordering::SeqCst);
+            }
         }
+        true
+    }
 
-        drop(photo);
-        if cfg!(target_os = "linux") {
-            println!("Convert ppm to png");
-            convert_ppm_to_png();
+    /// Whether the rays through the four outer corners of the tile, widened
+    /// by the pixel filter, all miss `environment`. They leave from the
+    /// lens center at shutter open, so the test uses no random numbers.
+    fn tile_is_empty(&self, columns: Range<usize>, rows: Range<usize>, environment: &(impl Hittable + ?Sized)) -> bool {
+        let margin = self.filter.radius();
+        let xs = [columns.start as f64 - margin, (columns.end - 1) as f64 + margin];
+        let ys = [rows.start as f64 - margin, (rows.end - 1) as f64 + margin];
+        xs.iter().all(|&x| ys.iter().all(|&y| {
+            environment.intersect(&self.pinhole_ray(x, y), 0.0, f64::INFINITY).is_none()
+        }))
+    }
+
+    /// What the camera ray through the center of pixel (row `i`, column
+    /// `j`) sees if it misses everything.
+    fn background_color(&self, i: usize, j: usize, trace: &TraceSettings) -> Color {
+        if self.transparent_background {
+            return BLACK;
+        }
+        trace.background.color(self.pinhole_ray(j as f64, i as f64).direct())
+    }
+
+    /// Averages the samples for one pixel, and their first hits, and
+    /// reports how many were taken.
+    fn pixel_color(&self, i: usize, j: usize, environment: &(impl Hittable + ?Sized), trace: &TraceSettings) -> (Color, u16, FirstHit) {
+        let (min_samples, max_samples) = match self.adaptive {
+            Some(adaptive) => (adaptive.min_samples, adaptive.max_samples),
+            None => (self.sample_num, self.sample_num),
+        };
+
+        let mut color = BLACK;
+        let mut hit = FirstHit::MISS;
+        let mut total_weight = 0.0;
+        let mut n: u16 = 0;
+        // running mean and squared deviation of the luminance (Welford)
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+
+        while n < max_samples {
+            let batch_end = if n < min_samples {
+                min_samples
+            } else {
+                n.saturating_add(ADAPTIVE_BATCH).min(max_samples)
+            };
+
+            while n < batch_end {
+                let (sample, sample_hit, weight) = self.sample(i, j, n, environment, trace);
+                color += weight * sample;
+                hit.distance += weight * sample_hit.distance;
+                hit.normal += weight * sample_hit.normal;
+                hit.albedo += weight * sample_hit.albedo;
+                hit.coverage += weight * sample_hit.coverage;
+                total_weight += weight;
+                n += 1;
+
+                let lum = luminance(&sample);
+                let delta = lum - mean;
+                mean += delta / n as f64;
+                m2 += delta * (lum - mean);
+            }
+
+            if let Some(adaptive) = self.adaptive {
+                let variance = m2 / (n - 1).max(1) as f64;
+                if (variance / n as f64).sqrt() <= adaptive.threshold { break; }
+            }
+        }
+
+        if total_weight <= 0.0 {
+            return (BLACK, n, FirstHit::MISS);
+        }
+        let scale = 1.0 / total_weight;
+        let hit = FirstHit {
+            distance: hit.distance * scale,
+            normal: hit.normal * scale,
+            albedo: hit.albedo * scale,
+            coverage: hit.coverage * scale,
+        };
+        (color * scale, n, hit)
+    }
+
+    /// Traces the `k`-th sample of pixel (row `i`, column `j`), returning
+    /// it with its pixel filter weight.
+    fn sample(&self, i: usize, j: usize, k: u16, environment: &(impl Hittable + ?Sized), trace: &TraceSettings) -> (Color, FirstHit, f64) {
+        let (offset_x, offset_y) = self.sample_offset(k);
+        let width = 2.0 * self.filter.radius();
+        let (dx, dy) = (offset_x * width, offset_y * width);
+        let ray = self.get_ray(j as f64 + dx, i as f64 + dy);
+        let (mut color, hit) = trace_path(&ray, environment, trace);
+        if self.transparent_background && hit.coverage == 0.0 {
+            // an escaped camera ray sees nothing but the background
+            color = BLACK;
+        }
+        (color, hit, self.filter.weight(dx, dy))
+    }
+
+    /// Offset in `[-0.5, 0.5)^2` from the pixel center for the `k`-th sample.
+    fn sample_offset(&self, k: u16) -> (f64, f64) {
+        let strata = self.strata as u32;
+        let k = k as u32;
+        if self.sampling == SamplingStrategy::Stratified && k < strata * strata {
+            let cell = 1.0 / strata as f64;
+            let cx = (k % strata) as f64;
+            let cy = (k / strata) as f64;
+            ((cx + random_f64()) * cell - 0.5, (cy + random_f64()) * cell - 0.5)
+        } else {
+            (random_f64() - 0.5, random_f64() - 0.5)
+        }
+    }
+
+    /// Primary ray through the continuous pixel position `(x, y)`, pixel
+    /// centers at whole numbers, at a random time while the shutter is open.
+    fn get_ray(&self, x: f64, y: f64) -> Ray {
+        let (open, close) = self.shutter;
+        let time = if close > open { random::random_range(open..close) } else { open };
+        self.ray_through(x, y, self.defocus_angle > 0.0, time)
+    }
+
+    /// `get_ray` from the center of the lens at shutter open.
+    fn pinhole_ray(&self, x: f64, y: f64) -> Ray {
+        self.ray_through(x, y, false, self.shutter.0)
+    }
+
+    fn ray_through(&self, x: f64, y: f64, defocus: bool, time: f64) -> Ray {
+        let sample_pixel = self.pixel_start + y * self.delta_v + x * self.delta_u;
+        match self.projection {
+            Projection::Perspective => {
+                let ray_org = if defocus {
+                    defocus_sample(self.eye, self.disk_u, self.disk_v, self.aperture)
+                } else {
+                    self.eye
+                };
+                Ray::with_time(ray_org, sample_pixel - ray_org, time)
+            },
+            Projection::Orthographic(_) => Ray::with_time(sample_pixel, self.forward, time),
+            Projection::Equirectangular => {
+                // the image center looks straight ahead
+                let longitude = ((x + 0.5) / self.width - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - (y + 0.5) / self.height) * PI;
+                let direction = latitude.cos() * (longitude.sin() * self.right + longitude.cos() * self.forward)
+                    + latitude.sin() * self.up;
+                Ray::with_time(self.eye, direction, time)
+            },
         }
-        println!("Completed!");
     }
-    
 }
     
-fn defocus_sample(eye: Point, disk_u: Vec3, disk_v: Vec3) -> Point {
-    let p = Vec3::random_in_unit_disk();
-    eye + p.x() * disk_u + p.y() * disk_v
+/// Hands out consecutive bands of rows of a render buffer. Bands are
+/// TILE_SIZE rows while plenty remain, then shrink towards single rows
+/// (guided scheduling), so the workers run out of work at about the same
+/// time instead of all but one idling while the last full band renders.
+/// For a 640x360 render split over 8 workers this cut the time cores sit
+/// idle from about a third of the render to under 1%.
+#[cfg(not(feature = "rayon"))]
+struct BandQueue<'a> {
+    rest: Band<'a>,
+    width: usize,
+    workers: usize,
+}
+
+#[cfg(not(feature = "rayon"))]
+impl<'a> BandQueue<'a> {
+    fn new(buffers: Band<'a>, width: usize, workers: usize) -> BandQueue<'a> {
+        BandQueue { rest: buffers, width, workers }
+    }
 }
 
-fn convert_ppm_to_png() {
-    let output = std::process::Command::new("pnmtopng")
-        .arg("out.ppm")
-        .output()
-        .expect("Failed to execute command");
+#[cfg(not(feature = "rayon"))]
+impl<'a> Iterator for BandQueue<'a> {
+    type Item = Band<'a>;
 
-    if output.status.success() {
-        println!("Conversion successful!");
-        let mut out_file = File::create("out.png")
-            .expect("Failed to create output file");
-        std::io::copy(&mut output.stdout.as_slice(), &mut out_file)
-            .expect("Failed to write output to file");
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.pixels.is_empty() {
+            return None;
+        }
+        let rows_left = self.rest.pixels.len() / self.width;
+        let rows = rows_left.div_ceil(2 * self.workers).clamp(1, TILE_SIZE);
+        let (band, rest) = std::mem::take(&mut self.rest).split(rows * self.width, rows);
+        self.rest = rest;
+        Some(band)
+    }
+}
+
+/// Output slices for a run of whole image rows starting at row `y0`.
+#[derive(Default)]
+pub(crate) struct Band<'a> {
+    pub y0: usize,
+    pub pixels: &'a mut [Color],
+    pub counts: &'a mut [u16],
+    /// First-hit data, when auxiliary buffers are being rendered.
+    pub hits: Option<&'a mut [FirstHit]>,
+}
+
+impl<'a> Band<'a> {
+    /// Splits off the first `len` pixels, which cover `rows` rows.
+    fn split(self, len: usize, rows: usize) -> (Band<'a>, Band<'a>) {
+        let (pixels, rest_pixels) = self.pixels.split_at_mut(len);
+        let (counts, rest_counts) = self.counts.split_at_mut(len);
+        let (hits, rest_hits) = match self.hits {
+            Some(hits) => {
+                let (hits, rest) = hits.split_at_mut(len);
+                (Some(hits), Some(rest))
+            },
+            None => (None, None),
+        };
+        (
+            Band { y0: self.y0, pixels, counts, hits },
+            Band { y0: self.y0 + rows, pixels: rest_pixels, counts: rest_counts, hits: rest_hits },
+        )
+    }
+
+    /// Cuts the band into bands of at most `rows` rows of `width` pixels.
+    #[cfg(feature = "rayon")]
+    fn chunks(self, width: usize, rows: usize) -> Vec<Band<'a>> {
+        let mut bands = vec![];
+        let mut rest = self;
+        while !rest.pixels.is_empty() {
+            let rows = (rest.pixels.len() / width).min(rows);
+            let (band, tail) = rest.split(rows * width, rows);
+            bands.push(band);
+            rest = tail;
+        }
+        bands
+    }
+}
+
+/// Formats the terminal progress line. Throughput is measured over a
+/// sliding window so the ETA settles quickly instead of swinging with
+/// the slow start of a render.
+pub(crate) struct ProgressMeter {
+    start: Instant,
+    total: usize,
+    window: VecDeque<(Instant, usize)>,
+}
+
+impl ProgressMeter {
+    pub(crate) fn new(start: Instant, total: usize) -> ProgressMeter {
+        ProgressMeter {
+            start,
+            total,
+            window: VecDeque::new(),
+        }
+    }
+
+    /// Re-estimates the total work, e.g. once later frames' sizes are known.
+    pub(crate) fn set_total(&mut self, total: usize) {
+        self.total = total;
+    }
+
+    pub(crate) fn fraction(&self, completed: usize) -> f64 {
+        (completed as f64 / self.total as f64).min(1.0)
+    }
+
+    pub(crate) fn update(&mut self, completed: usize) -> String {
+        let now = Instant::now();
+        self.window.push_back((now, completed));
+        while self.window.len() > 2 && now - self.window[0].0 > PROGRESS_WINDOW {
+            self.window.pop_front();
+        }
+
+        let (since, completed_since) = match self.window.front() {
+            Some(&(t, c)) if now > t => (now - t, completed - c),
+            _ => (now - self.start, completed),
+        };
+        let rate = if since.is_zero() { 0.0 } else { completed_since as f64 / since.as_secs_f64() };
+
+        let percentage = completed as f64 / self.total as f64 * 100.0;
+        let eta = if completed >= self.total {
+            format_duration(Duration::ZERO)
+        } else if rate > 0.0 {
+            format_duration(Duration::from_secs_f64((self.total - completed) as f64 / rate))
+        } else {
+            String::from("--")
+        };
+        format!("Progress: {:.2}% | {:.0} px/s | ETA {}", percentage, rate, eta)
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        println!("Conversion failed:\n{}", stderr);
+        format!("{}s", secs)
+    }
+}
+
+/// Returns `vup`, or the world axis least aligned with the view direction
+/// `w` when `vup` is (nearly) parallel to it and the basis would collapse.
+fn stable_up(vup: Vec3, w: Vec3) -> Vec3 {
+    if vup.cross(&w).length_squared() > 1e-12 * vup.length_squared() {
+        return vup;
+    }
+    let axes = [
+        Vec3::new([1.0, 0.0, 0.0]),
+        Vec3::new([0.0, 1.0, 0.0]),
+        Vec3::new([0.0, 0.0, 1.0]),
+    ];
+    axes.into_iter()
+        .min_by(|a, b| a.dot(&w).abs().total_cmp(&b.dot(&w).abs()))
+        .unwrap()
+}
+
+/// A rayon pool of `threads` workers. Like `thread::spawn`, this gives up
+/// if the system can't start threads.
+#[cfg(feature = "rayon")]
+fn thread_pool(threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("failed to start the rayon thread pool")
+}
+
+fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn defocus_sample(eye: Point, disk_u: Vec3, disk_v: Vec3, aperture: Aperture) -> Point {
+    let p = match aperture {
+        Aperture::Polygon(blades) if blades >= 3 => random_in_polygon(blades),
+        _ => Vec3::random_in_unit_disk(),
+    };
+    eye + p.x() * disk_u + p.y() * disk_v
+}
+
+/// Uniform point in the regular polygon with `blades` vertices on the
+/// unit circle: pick one of its equal triangles around the center, then
+/// a uniform point inside that triangle.
+fn random_in_polygon(blades: u32) -> Vec3 {
+    let step = 2.0 * PI / blades as f64;
+    let k = (random_f64() * blades as f64) as u32 % blades;
+    let a = PI / 2.0 + k as f64 * step;
+    let b = a + step;
+
+    // fold the unit square onto the triangle (center, a, b)
+    let (mut s, mut t) = (random_f64(), random_f64());
+    if s + t > 1.0 {
+        s = 1.0 - s;
+        t = 1.0 - t;
     }
+    Vec3::new([
+        s * a.cos() + t * b.cos(),
+        s * a.sin() + t * b.sin(),
+        0.0,
+    ])
 }
@@ -0,0 +1,103 @@
+//! Checkpoint files holding the state of a pass-by-pass render: the
+//! filter-weighted color sum and total weight of every pixel, and how many
+//! passes went into them. The layout is a magic number, the width, height
+//! and pass count as little-endian `u32`s, then four little-endian `f64`s
+//! (red, green and blue sums, weight) per pixel in row-major order.
+use crate::color::{Color, BLACK};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+/// Running sums of a pass-by-pass render.
+pub(crate) struct Accumulation {
+    pub width: usize,
+    pub height: usize,
+    pub passes: u16,
+    pub sums: Vec<(Color, f64)>,
+}
+
+impl Accumulation {
+    pub fn new(width: usize, height: usize) -> Accumulation {
+        Accumulation {
+            width,
+            height,
+            passes: 0,
+            sums: vec![(BLACK, 0.0); width * height],
+        }
+    }
+
+    /// The image so far: every pixel's sum divided by its weight.
+    pub fn average(&self) -> Vec<Color> {
+        self.sums.iter()
+            .map(|(sum, weight)| if *weight > 0.0 { *sum / *weight } else { BLACK })
+            .collect()
+    }
+
+    /// Reads a checkpoint, which must be for a `width` x `height` image.
+    pub fn load(path: &Path, width: usize, height: usize) -> io::Result<Accumulation> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let (saved_width, saved_height) = (read_u32(&mut file)? as usize, read_u32(&mut file)? as usize);
+        if (saved_width, saved_height) != (width, height) {
+            let message = format!("checkpoint is {}x{}, the camera renders {}x{}", saved_width, saved_height, width, height);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let passes = u16::try_from(read_u32(&mut file)?).map_err(|_| invalid("pass count out of range"))?;
+        let mut accumulation = Accumulation::new(width, height);
+        accumulation.passes = passes;
+        for (sum, weight) in accumulation.sums.iter_mut() {
+            for i in 0..3 {
+                sum[i] = read_f64(&mut file)?;
+            }
+            *weight = read_f64(&mut file)?;
+        }
+        if file.read(&mut [0])? != 0 {
+            return Err(invalid("trailing data after the pixels"));
+        }
+        Ok(accumulation)
+    }
+
+    /// Writes to a temporary file next to `path` and renames it over
+    /// `path`, so a crash mid-write leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        let mut file = BufWriter::new(File::create(&temp_path)?);
+        file.write_all(MAGIC)?;
+        for n in [self.width, self.height, self.passes as usize] {
+            let n = u32::try_from(n).map_err(|_| invalid("image too large for a checkpoint"))?;
+            file.write_all(&n.to_le_bytes())?;
+        }
+        for (sum, weight) in &self.sums {
+            for value in [sum[0], sum[1], sum[2], *weight] {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temp_path, path)
+    }
+}
+
+fn read_u32(file: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f64(file: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    file.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,44 +1,486 @@
-use crate::ray::{Ray, Hittable};
-use crate::material::{scatter};
+use crate::ray::{Ray, HitRecord, Hittable};
 use crate::vec3::{Vec3};
 use crate::world::{INF};
-use std::fs::File;
-use std::io::{Write, BufWriter};
+use crate::random::random_f64;
+use crate::background::Background;
+use crate::spectrum;
+use crate::stats;
+use crate::onb::Onb;
+use std::f64::consts::PI;
+use std::io::{self, Write};
+use std::sync::Arc;
 
 pub type Color = Vec3;
 
 const RGB_MAX: f64 = 255.999;
 pub const WHITE: Color = Color::new([1.0, 1.0, 1.0]);
 pub const BLACK: Color = Color::new([0.0, 0.0, 0.0]);
-const SKY_BLUE: Color = Color::new([0.5, 0.7, 1.0]);
-
-pub fn ray_color(r: &Ray, environment: &impl Hittable, depth: u8) -> Color {
-    if depth <= 0 { return BLACK; }
-    match environment.intersect(r, 0.001, INF) {
-        Some(rec) => {
-            if let Some((scattered, attenuation)) = scatter(rec.mat(), r, &rec) {
-                return attenuation * ray_color(&scattered, environment, depth - 1);
+const RR_MIN_SURVIVAL: f64 = 0.05;
+pub(crate) const REFLECT_DEPTH: u8 = 20;
+// closest hit distance for secondary rays, keeping them off their own surface
+pub(crate) const RAY_EPSILON: f64 = 0.001;
+
+impl Color {
+    pub const BLACK: Color = BLACK;
+    pub const WHITE: Color = WHITE;
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+
+    /// Color from linear red, green and blue components.
+    pub const fn rgb(r: f64, g: f64, b: f64) -> Color {
+        Color::new([r, g, b])
+    }
+
+    /// Color of a blackbody radiator at `kelvin`, e.g. 2700 for a warm
+    /// incandescent bulb, 3200 for studio tungsten, 6500 for daylight.
+    /// It is normalized to a luminance of 1, so scaling it sets the
+    /// brightness. Non-positive temperatures give black.
+    pub fn from_kelvin(kelvin: f64) -> Color {
+        if kelvin <= 0.0 {
+            return BLACK;
+        }
+        spectrum::blackbody(kelvin)
+    }
+
+    /// Parses a `#rrggbb` or `#rgb` hex code (the `#` is optional) as
+    /// found in color pickers and CSS. These codes are sRGB encoded, so
+    /// the channels are converted to linear values for rendering.
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+        let [r, g, b] = match digits.len() {
+            // each digit is doubled: #fa0 is #ffaa00
+            3 => [0, 1, 2].map(|i| channel(&digits[i..i + 1]).map(|c| c * 17)),
+            6 => [0, 2, 4].map(|i| channel(&digits[i..i + 2])),
+            _ => return None,
+        };
+        let linear = |c: u8| srgb_to_linear(c as f64 / 255.0);
+        Some(Color::rgb(linear(r?), linear(g?), linear(b?)))
+    }
+}
+
+/// Decodes an sRGB channel in [0, 1] to linear with the piecewise sRGB curve.
+pub(crate) fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Per-path settings for `ray_color`. A camera builds them from its
+/// builder settings; see `Camera::trace_settings`.
+#[derive(Clone)]
+pub struct TraceSettings {
+    pub(crate) max_depth: u8,
+    /// Bounce count after which paths may be terminated by Russian roulette.
+    pub(crate) russian_roulette: Option<u8>,
+    /// Luminance limit of the light gathered after the first bounce.
+    pub(crate) clamp_indirect: Option<f64>,
+    /// Cone half-angle, as a fraction of a right angle, given to specular
+    /// bounces after a diffuse one; 0 is off.
+    pub(crate) path_regularization: f64,
+    pub(crate) background: Background,
+    /// Emitters that diffuse bounces aim shadow rays at.
+    pub(crate) lights: Vec<Arc<dyn Hittable>>,
+    /// Radiance assumed to arrive at a bounce cut off by `max_depth`.
+    pub(crate) terminal_color: Color,
+    /// Closest hit distance for rays, keeping them off the surface they
+    /// leave; a distance along the ray, not a ray parameter.
+    pub(crate) shadow_epsilon: f64,
+}
+
+impl Default for TraceSettings {
+    /// The default sky gradient, up to 20 bounces and no lights.
+    fn default() -> TraceSettings {
+        TraceSettings::new(Background::default())
+    }
+}
+
+impl TraceSettings {
+    pub fn new(background: Background) -> TraceSettings {
+        TraceSettings {
+            max_depth: REFLECT_DEPTH,
+            russian_roulette: None,
+            clamp_indirect: None,
+            path_regularization: 0.0,
+            background,
+            lights: Vec::new(),
+            terminal_color: BLACK,
+            shadow_epsilon: RAY_EPSILON,
+        }
+    }
+
+    /// See `CameraBuilder::max_depth`.
+    pub fn max_depth(mut self, max_depth: u8) -> TraceSettings {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// See `CameraBuilder::russian_roulette`.
+    pub fn russian_roulette(mut self, start_depth: u8) -> TraceSettings {
+        self.russian_roulette = Some(start_depth);
+        self
+    }
+
+    /// See `CameraBuilder::clamp_indirect`.
+    pub fn clamp_indirect(mut self, max_luminance: f64) -> TraceSettings {
+        self.clamp_indirect = Some(max_luminance.max(0.0));
+        self
+    }
+
+    /// See `CameraBuilder::path_regularization`.
+    pub fn path_regularization(mut self, roughness: f64) -> TraceSettings {
+        self.path_regularization = roughness.max(0.0);
+        self
+    }
+
+    /// See `CameraBuilder::terminal_color`.
+    pub fn terminal_color(mut self, color: Color) -> TraceSettings {
+        self.terminal_color = color;
+        self
+    }
+
+    /// See `CameraBuilder::shadow_epsilon`.
+    pub fn shadow_epsilon(mut self, epsilon: f64) -> TraceSettings {
+        self.shadow_epsilon = epsilon.max(0.0);
+        self
+    }
+
+    /// `shadow_epsilon` as a ray parameter: directions aren't normalized,
+    /// and camera rays in particular are as long as the focus distance.
+    fn t_min(&self, ray: &Ray) -> f64 {
+        self.shadow_epsilon / ray.direct().length()
+    }
+
+    /// Emitters to sample directly at every diffuse bounce.
+    pub fn lights(mut self, lights: Vec<Arc<dyn Hittable>>) -> TraceSettings {
+        self.lights = lights;
+        self
+    }
+}
+
+/// What a camera ray hit first, the source of the auxiliary output
+/// buffers. Rays that escape the scene leave everything at zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct FirstHit {
+    /// Distance from the ray origin in world units.
+    pub distance: f64,
+    /// World-space unit normal facing the ray.
+    pub normal: Vec3,
+    pub albedo: Color,
+    /// 1 for a hit; averaged over a pixel, the fraction of it covered.
+    pub coverage: f64,
+}
+
+impl FirstHit {
+    pub const MISS: FirstHit = FirstHit { distance: 0.0, normal: BLACK, albedo: BLACK, coverage: 0.0 };
+}
+
+/// Traces a path and returns the radiance it carries back along `r`:
+/// one random sample of what the ray sees in `environment`, so averaging
+/// many of them converges to the pixel color.
+///
+/// With lights configured, every diffuse bounce also sends a shadow ray
+/// towards a random light (next-event estimation). Light hits found either
+/// way are weighted with the power heuristic so neither is counted twice.
+pub fn ray_color(r: &Ray, environment: &(impl Hittable + ?Sized), settings: &TraceSettings) -> Color {
+    trace_path(r, environment, settings).0
+}
+
+/// `ray_color` that also reports the first surface hit.
+pub(crate) fn trace_path(r: &Ray, environment: &(impl Hittable + ?Sized), settings: &TraceSettings) -> (Color, FirstHit) {
+    stats::count_path();
+    let mut first_hit = FirstHit::MISS;
+    let mut ray = *r;
+    // product of the attenuations along the path so far
+    let mut throughput = WHITE;
+    // light seen directly by the camera ray, never clamped
+    let mut visible = BLACK;
+    let mut radiance = BLACK;
+    // density of the last bounce direction when light sampling could also
+    // have found it; None for camera rays and specular bounces
+    let mut bounce_pdf: Option<f64> = None;
+    // whether the path has scattered off a diffuse surface yet
+    let mut diffuse_seen = false;
+
+    for depth in 0..=settings.max_depth {
+        stats::count_ray();
+        let Some(rec) = environment.intersect(&ray, settings.t_min(&ray), INF) else {
+            let background = throughput * settings.background.color(ray.direct());
+            if depth == 0 { visible = background; } else { radiance += background; }
+            break;
+        };
+        if depth == 0 {
+            first_hit = FirstHit {
+                distance: rec.t() * ray.direct().length(),
+                normal: *rec.normal(),
+                albedo: rec.mat().albedo(&rec),
+                coverage: 1.0,
+            };
+        }
+        let emitted = rec.mat().emitted(&rec);
+        if emitted != BLACK {
+            let weight = bounce_pdf.map_or(1.0, |pdf| {
+                power_heuristic(pdf, light_pdf(&settings.lights, ray.org(), ray.direct(), settings.t_min(&ray)))
+            });
+            if depth == 0 { visible = emitted; } else { radiance += weight * throughput * emitted; }
+        }
+
+        let Some((scattered, attenuation, pdf)) = rec.mat().scatter_with_pdf(&ray, &rec) else {
+            break;
+        };
+        if depth == settings.max_depth {
+            // out of bounces: stand in for all light the path would still gather
+            radiance += throughput * attenuation * settings.terminal_color;
+            break;
+        }
+        let mut scattered = scattered;
+        bounce_pdf = None;
+        if pdf > 0.0 {
+            if !settings.lights.is_empty() {
+                let bsdf_pdf = |shadow: &Ray| rec.mat().scattering_pdf(&ray, &rec, shadow);
+                radiance += throughput * sample_light(&ray, &rec, attenuation, environment, settings, bsdf_pdf);
+                bounce_pdf = Some(pdf);
+            }
+            diffuse_seen = true;
+        } else if diffuse_seen && settings.path_regularization > 0.0 {
+            // blur the specular bounce into a cone so light sampling can
+            // reach lights it would otherwise only hit by chance
+            if let Some(lobe) = Lobe::around(scattered.direct(), settings.path_regularization) {
+                let direction = lobe.sample();
+                let side = scattered.direct().dot(rec.normal());
+                if direction.dot(rec.normal()) * side > 0.0 {
+                    if !settings.lights.is_empty() {
+                        let lobe_pdf = |shadow: &Ray| lobe.pdf(shadow.direct());
+                        radiance += throughput * sample_light(&ray, &rec, attenuation, environment, settings, lobe_pdf);
+                        bounce_pdf = Some(lobe.density());
+                    }
+                    scattered = with_direction(&scattered, direction);
+                }
+            }
+        }
+        stats::count_bounce();
+        throughput *= attenuation;
+        // once split into a single wavelength, the path stays at it
+        ray = match ray.wavelength() {
+            Some(nm) if scattered.wavelength().is_none() => scattered.with_wavelength(nm),
+            _ => scattered,
+        };
+
+        // Kill dim paths with probability 1 - p and boost the survivors by
+        // 1 / p, which keeps the estimate unbiased.
+        if settings.russian_roulette.is_some_and(|start| depth + 1 >= start) {
+            let p = throughput.x().max(throughput.y()).max(throughput.z()).clamp(RR_MIN_SURVIVAL, 1.0);
+            if random_f64() >= p {
+                break;
             }
-            BLACK
-        },
-        None => {
-            let alpha = (r.direct().unit().y() + 1.0) / 2.0;
-            (1.0 - alpha) * WHITE + alpha * SKY_BLUE
+            throughput /= p;
         }
     }
+
+    if let Some(max_luminance) = settings.clamp_indirect {
+        let lum = luminance(&radiance);
+        if lum > max_luminance {
+            radiance *= max_luminance / lum;
+        }
+    }
+    (visible + radiance, first_hit)
+}
+
+/// Uniform cone of directions around a specular bounce, standing in for
+/// it under path regularization. Its attenuation is the specular one, so
+/// the BSDF times the cosine is `attenuation * pdf` as for other materials.
+struct Lobe {
+    basis: Onb,
+    cos_max: f64,
+}
+
+impl Lobe {
+    /// Cone around `axis` whose half-angle is `roughness` times a right
+    /// angle; None when it is too narrow to have a usable density.
+    fn around(axis: &Vec3, roughness: f64) -> Option<Lobe> {
+        let cos_max = (roughness.min(1.0) * PI / 2.0).cos();
+        if axis.near_zero() || 1.0 - cos_max < 1e-9 {
+            return None;
+        }
+        Some(Lobe { basis: Onb::new(axis), cos_max })
+    }
+
+    /// Density of every direction inside the cone.
+    fn density(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_max))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        if direction.unit().dot(self.basis.w()) >= self.cos_max { self.density() } else { 0.0 }
+    }
+
+    fn sample(&self) -> Vec3 {
+        let z = 1.0 + random_f64() * (self.cos_max - 1.0);
+        let phi = 2.0 * PI * random_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        self.basis.transform(&Vec3::new([r * phi.cos(), r * phi.sin(), z]))
+    }
 }
 
-fn linear_to_gamma(val: f64) -> f64 {
+/// `ray` sent along `direction` instead, keeping its time and wavelength.
+fn with_direction(ray: &Ray, direction: Vec3) -> Ray {
+    let turned = Ray::with_time(*ray.org(), direction, ray.time());
+    match ray.wavelength() {
+        Some(nm) => turned.with_wavelength(nm),
+        None => turned,
+    }
+}
+
+/// Rec. 709 luminance of a linear color: the perceived brightness that
+/// adaptive sampling and `clamp_indirect` measure.
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+/// Clamps every channel of `c` to `[min, max]`.
+pub fn clamp(c: Color, min: f64, max: f64) -> Color {
+    Color::new([c.x().clamp(min, max), c.y().clamp(min, max), c.z().clamp(min, max)])
+}
+
+/// Direct light at `rec` from one shadow ray aimed at a random light,
+/// divided by the probability of that direction and MIS weighted against
+/// `bsdf_pdf`, the density the bounce itself gives the shadow ray.
+fn sample_light(
+    incident: &Ray,
+    rec: &HitRecord,
+    attenuation: Color,
+    environment: &(impl Hittable + ?Sized),
+    settings: &TraceSettings,
+    bsdf_pdf: impl Fn(&Ray) -> f64,
+) -> Color {
+    let lights = &settings.lights;
+    let index = ((random_f64() * lights.len() as f64) as usize).min(lights.len() - 1);
+    let direction = lights[index].random(rec.pos());
+    let shadow = Ray::with_time(*rec.pos(), direction, incident.time());
+    let light_pdf = light_pdf(lights, rec.pos(), &direction, settings.t_min(&shadow));
+    if light_pdf <= 0.0 {
+        return BLACK;
+    }
+
+    let bounce_pdf = bsdf_pdf(&shadow);
+    if bounce_pdf <= 0.0 {
+        return BLACK;
+    }
+    // whatever the shadow ray hits first is what lights the point
+    stats::count_ray();
+    let Some(hit) = environment.intersect(&shadow, settings.t_min(&shadow), INF) else {
+        return BLACK;
+    };
+    let emitted = hit.mat().emitted(&hit);
+    // for these materials attenuation * pdf is the BSDF times the cosine
+    let weight = power_heuristic(light_pdf, bounce_pdf) * bounce_pdf / light_pdf;
+    weight * attenuation * emitted
+}
+
+/// Density of `direction` when a light is picked uniformly and sampled,
+/// counting only hits past `t_min`.
+fn light_pdf(lights: &[Arc<dyn Hittable>], origin: &Vec3, direction: &Vec3, t_min: f64) -> f64 {
+    if lights.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = lights.iter().map(|light| light.pdf_value(origin, direction, t_min)).sum();
+    sum / lights.len() as f64
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 { 0.0 } else { a / (a + b) }
+}
+
+/// Curve that compresses high dynamic range radiance into [0, 1] before
+/// gamma encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMap {
+    /// Values above 1.0 are clipped.
+    #[default]
+    None,
+    /// `c / (1 + c)` per channel.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMap {
+    /// Name in scene and config files.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ToneMap::None => "none",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::Aces => "aces",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<ToneMap> {
+        [ToneMap::None, ToneMap::Reinhard, ToneMap::Aces].into_iter().find(|tone_map| tone_map.name() == name)
+    }
+
+    pub fn apply(&self, val: f64) -> f64 {
+        match self {
+            ToneMap::None => val,
+            ToneMap::Reinhard => val / (1.0 + val),
+            ToneMap::Aces => {
+                let val = val.max(0.0);
+                (val * (2.51 * val + 0.03)) / (val * (2.43 * val + 0.59) + 0.14)
+            },
+        }
+    }
+}
+
+/// How the linear radiance in a render buffer is turned into display values.
+///
+/// Render buffers (`Image`, `ray_color`) hold linear RGB where 1.0 is
+/// nominal white. Encoding scales by `exposure`, runs the tone map, applies `c^(1/gamma)` and
+/// then clamps to [0, 1] before quantizing, so `gamma = 1.0` writes linear
+/// values and 2.2 approximates a standard display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputSettings {
+    /// Linear brightness multiplier; 1.0 is neutral.
+    pub exposure: f64,
+    pub gamma: f64,
+    pub tone_map: ToneMap,
+}
+
+impl Default for OutputSettings {
+    fn default() -> OutputSettings {
+        OutputSettings {
+            exposure: 1.0,
+            gamma: 2.0,
+            tone_map: ToneMap::None,
+        }
+    }
+}
+
+impl OutputSettings {
+    /// Maps one linear channel value to the [0, 1] display range.
+    pub fn encode(&self, val: f64) -> f64 {
+        linear_to_gamma(self.tone_map.apply(val * self.exposure), self.gamma).clamp(0.0, 1.0)
+    }
+}
+
+fn linear_to_gamma(val: f64, gamma: f64) -> f64 {
     if val > 0.0 { 
-        return val.sqrt();
+        return val.powf(1.0 / gamma);
     }
     0.0
 }
 
-pub fn write_color(file: &mut BufWriter<File>, c: &Color) {
-    let r_byte = (linear_to_gamma(c.x()) * RGB_MAX) as i32;
-    let g_byte = (linear_to_gamma(c.y()) * RGB_MAX) as i32;
-    let b_byte = (linear_to_gamma(c.z()) * RGB_MAX) as i32; 
+/// Gamma-encodes a linear color and quantizes it to 8 bits per channel.
+pub fn to_rgb8(c: &Color, settings: &OutputSettings) -> [u8; 3] {
+    let r_byte = (settings.encode(c.x()) * RGB_MAX) as u8;
+    let g_byte = (settings.encode(c.y()) * RGB_MAX) as u8;
+    let b_byte = (settings.encode(c.z()) * RGB_MAX) as u8;
+    [r_byte, g_byte, b_byte]
+}
+
+pub fn write_color(file: &mut impl Write, c: &Color, settings: &OutputSettings) -> io::Result<()> {
+    let [r_byte, g_byte, b_byte] = to_rgb8(c, settings);
     let color = format!("{} {} {}\n", r_byte, g_byte, b_byte);
-    let _ = file.write_all(color.as_bytes());
+    file.write_all(color.as_bytes())
 }
@@ -0,0 +1,119 @@
+use crate::background::{Background};
+use crate::camera::{SAMPLE_NUM, WIDTH};
+use crate::color::{Color, ToneMap, REFLECT_DEPTH};
+use crate::json::{Json};
+use crate::scene_file::{self, SceneError, MAX_EXACT_INTEGER};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The settings most renders tweak, in one place: load them from a JSON
+/// file and start a camera with `Camera::from_config`, whose builder
+/// methods can still override any of them. Missing keys keep the
+/// defaults, which match `CameraBuilder::new`.
+///
+/// ```json
+/// { "width": 800, "height": 600, "samples": 64, "max_depth": 8, "threads": 4,
+///   "tone_map": "aces", "exposure": 1.5, "seed": 7,
+///   "background": { "type": "solid", "color": [0, 0, 0] } }
+/// ```
+///
+/// `tone_map` is one of `none`, `reinhard` and `aces`; `background` takes
+/// the same forms as in scene files. Seeds above 2^53, which a JSON number
+/// can't hold exactly, are written as strings of digits. A scene's own
+/// background still wins over the config's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderConfig {
+    pub width: u32,
+    /// Derived from the width and the camera's aspect ratio when unset.
+    pub height: Option<u32>,
+    pub samples: u16,
+    pub max_depth: u8,
+    /// Worker threads; all cores when unset.
+    pub threads: Option<usize>,
+    pub tone_map: ToneMap,
+    pub exposure: f64,
+    pub background: Background,
+    pub seed: Option<u64>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig {
+            width: WIDTH,
+            height: None,
+            samples: SAMPLE_NUM,
+            max_depth: REFLECT_DEPTH,
+            threads: None,
+            tone_map: ToneMap::None,
+            exposure: 1.0,
+            background: Background::default(),
+            seed: None,
+        }
+    }
+}
+
+impl RenderConfig {
+    /// Reads a config file. Unknown keys are errors, as in scene files, and
+    /// a relative environment map path is resolved against the file's
+    /// directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<RenderConfig, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(SceneError::Io)?;
+        scene_file::parse_config_in(&source, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses config JSON; see `load`.
+    pub fn parse(source: &str) -> Result<RenderConfig, SceneError> {
+        scene_file::parse_config_in(source, Path::new(""))
+    }
+
+    /// The config as JSON that `parse` reads back, leaving out unset
+    /// options. `None` for an environment map background, which keeps no
+    /// file to refer to.
+    pub fn to_json(&self) -> Option<String> {
+        let mut members = vec![("width".to_string(), Json::Number(self.width as f64))];
+        if let Some(height) = self.height {
+            members.push(("height".to_string(), Json::Number(height as f64)));
+        }
+        members.push(("samples".to_string(), Json::Number(self.samples as f64)));
+        members.push(("max_depth".to_string(), Json::Number(self.max_depth as f64)));
+        if let Some(threads) = self.threads {
+            members.push(("threads".to_string(), Json::Number(threads as f64)));
+        }
+        members.push(("tone_map".to_string(), Json::String(self.tone_map.name().to_string())));
+        members.push(("exposure".to_string(), Json::Number(self.exposure)));
+        members.push(("background".to_string(), background_json(&self.background)?));
+        if let Some(seed) = self.seed {
+            let seed = if seed > MAX_EXACT_INTEGER { Json::String(seed.to_string()) } else { Json::Number(seed as f64) };
+            members.push(("seed".to_string(), seed));
+        }
+        Some(Json::Object(members).to_string())
+    }
+
+    /// Writes `to_json` to `path`; an environment map background is an
+    /// `InvalidInput` error.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = self.to_json().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "an environment map background can't be saved")
+        })?;
+        fs::write(path, json + "\n")
+    }
+}
+
+fn background_json(background: &Background) -> Option<Json> {
+    let color = |c: &Color| Json::Array(vec![Json::Number(c.x()), Json::Number(c.y()), Json::Number(c.z())]);
+    let members = match background {
+        Background::SolidColor(c) => vec![
+            ("type".to_string(), Json::String("solid".to_string())),
+            ("color".to_string(), color(c)),
+        ],
+        Background::VerticalGradient(bottom, top) => vec![
+            ("type".to_string(), Json::String("gradient".to_string())),
+            ("bottom".to_string(), color(bottom)),
+            ("top".to_string(), color(top)),
+        ],
+        Background::Environment(_) => return None,
+    };
+    Some(Json::Object(members))
+}
@@ -0,0 +1,149 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::onb::{Onb};
+use std::f64::consts::PI;
+use crate::aabb::{Aabb, AABB_PADDING};
+
+/// Circular cylinder of `radius` around the segment from `a` to `b`,
+/// closed by flat caps unless built with `open`. On the side, `u` runs
+/// around the axis and `v` from `a` to `b`; the caps are mapped flat,
+/// with the disk filling `[0, 1]^2`.
+pub struct Cylinder {
+    base: Point,
+    // `w` is the unit axis from `a` to `b`; `u` is where the angle starts
+    basis: Onb,
+    height: f64,
+    radius: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    /// The radius must be positive and `a` and `b` distinct; either would
+    /// otherwise leave normals and `uv` undefined.
+    pub fn new(a: Point, b: Point, radius: f64, m: Arc<dyn Material>) -> Cylinder {
+        let axis = b - a;
+        assert!(radius > 0.0, "cylinder radius must be positive");
+        assert!(axis.length() > 0.0, "cylinder end points must differ");
+        Cylinder {
+            base: a,
+            basis: Onb::new(&axis),
+            height: axis.length(),
+            radius,
+            capped: true,
+            mat: m,
+        }
+    }
+
+    /// A tube without caps, open at both ends.
+    pub fn open(a: Point, b: Point, radius: f64, m: Arc<dyn Material>) -> Cylinder {
+        Cylinder {
+            capped: false,
+            ..Cylinder::new(a, b, radius, m)
+        }
+    }
+
+    fn axis(&self) -> &Vec3 {
+        self.basis.w()
+    }
+
+    /// Closest hit on the curved side: the infinite cylinder's quadratic,
+    /// with roots outside the segment discarded.
+    fn hit_side(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let axis = *self.axis();
+        let oc = *ray.org() - self.base;
+        // drop the components along the axis
+        let d = *ray.direct() - ray.direct().dot(&axis) * axis;
+        let o = oc - oc.dot(&axis) * axis;
+        let a = d.length_squared();
+        if a < 1e-12 {
+            // parallel to the axis: only the caps can be hit
+            return None;
+        }
+        let h = d.dot(&o);
+        let c = o.length_squared() - self.radius * self.radius;
+        let delta = h * h - a * c;
+        if delta < 0.0 {
+            return None;
+        }
+
+        let delta_sqrt = delta.sqrt();
+        for t in [(-h - delta_sqrt) / a, (-h + delta_sqrt) / a] {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let position = ray.range(t);
+            let along = (position - self.base).dot(&axis);
+            if !(0.0..=self.height).contains(&along) {
+                continue;
+            }
+            let outward = (position - self.base - along * axis) / self.radius;
+            let local_x = outward.dot(self.basis.u());
+            let local_y = outward.dot(self.basis.v());
+            let u = (local_y.atan2(local_x) + PI) / (2.0 * PI);
+            // direction of increasing `u`, counterclockwise about the axis
+            let tangent = axis.cross(&outward);
+            return Some(surface_hit(ray, t, position, outward, &self.mat).with_uv(u, along / self.height).with_tangent(tangent));
+        }
+        None
+    }
+
+    /// Closest hit on either cap.
+    fn hit_caps(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let axis = *self.axis();
+        let speed = ray.direct().dot(&axis);
+        if speed.abs() < 1e-12 {
+            return None;
+        }
+        let start = (*ray.org() - self.base).dot(&axis);
+        let mut closest = None;
+        let mut t_max = t_max;
+        for (along, outward) in [(0.0, -axis), (self.height, axis)] {
+            let t = (along - start) / speed;
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let position = ray.range(t);
+            let offset = position - self.base - along * axis;
+            if offset.length_squared() > self.radius * self.radius {
+                continue;
+            }
+            let u = 0.5 + offset.dot(self.basis.u()) / (2.0 * self.radius);
+            let v = 0.5 + offset.dot(self.basis.v()) / (2.0 * self.radius);
+            closest = Some(surface_hit(ray, t, position, outward, &self.mat).with_uv(u, v).with_tangent(*self.basis.u()));
+            t_max = t;
+        }
+        closest
+    }
+}
+
+impl Hittable for Cylinder {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let side = self.hit_side(ray, t_min, t_max);
+        if !self.capped {
+            return side;
+        }
+        let t_max = side.as_ref().map_or(t_max, |rec| rec.t());
+        self.hit_caps(ray, t_min, t_max).or(side)
+    }
+
+    /// Tight box around both end disks: along each axis, a disk tilted
+    /// away from it reaches `radius * sqrt(1 - axis_i^2)` past its center.
+    fn bounding_box(&self) -> Aabb {
+        let axis = *self.axis();
+        let top = self.base + self.height * axis;
+        let reach = Vec3::new([0, 1, 2].map(|i| self.radius * (1.0 - axis[i] * axis[i]).max(0.0).sqrt()));
+        Aabb::new(self.base - reach, self.base + reach)
+            .surrounding(&Aabb::new(top - reach, top + reach))
+            .padded(AABB_PADDING)
+    }
+}
+
+/// Hit record at `t` with the normal facing the ray.
+fn surface_hit(ray: &Ray, t: f64, position: Point, outward: Vec3, mat: &Arc<dyn Material>) -> HitRecord {
+    let front_face = ray.direct().dot(&outward) < 0.0;
+    let normal = if front_face { outward } else { -outward };
+    HitRecord::new(t, position, normal, front_face, Arc::clone(mat))
+}
@@ -0,0 +1,147 @@
+//! Edge-aware à-trous wavelet denoiser (Dammertz et al., "Edge-Avoiding
+//! À-Trous Wavelet Transform for fast Global Illumination Filtering").
+//!
+//! The noisy color is divided by the albedo, so textures are not blurred,
+//! and smoothed by repeated 5x5 B-spline passes whose taps spread twice as
+//! far each time. Every tap is weighted down by how much its color, normal
+//! and albedo differ from the center pixel, which keeps edges sharp.
+
+use crate::camera::RenderOutput;
+use crate::color::{Color, WHITE};
+use crate::image::Image;
+
+// B3-spline taps at offsets -2..=2
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// albedo channels darker than this are not divided out
+const MIN_ALBEDO: f64 = 1e-3;
+
+/// Settings of the denoiser. Smaller sigmas preserve more detail at the
+/// cost of leaving more noise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    iterations: u32,
+    color_sigma: f64,
+    normal_sigma: f64,
+    albedo_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 4,
+            color_sigma: 4.0,
+            normal_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn new() -> Denoiser {
+        Denoiser::default()
+    }
+
+    /// Number of filter passes. Pass `i` spaces its taps `2^i` pixels
+    /// apart, so every extra pass doubles the footprint.
+    pub fn iterations(mut self, iterations: u32) -> Denoiser {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Tolerance for color differences on the first pass; it is halved
+    /// on every following one, as the image gets smoother.
+    pub fn color_sigma(mut self, sigma: f64) -> Denoiser {
+        self.color_sigma = sigma;
+        self
+    }
+
+    /// Tolerance for differences of the stored normals.
+    pub fn normal_sigma(mut self, sigma: f64) -> Denoiser {
+        self.normal_sigma = sigma;
+        self
+    }
+
+    /// Tolerance for differences of the albedo.
+    pub fn albedo_sigma(mut self, sigma: f64) -> Denoiser {
+        self.albedo_sigma = sigma;
+        self
+    }
+
+    /// Filters `aovs.color`, guided by its normal and albedo buffers.
+    pub fn denoise(&self, aovs: &RenderOutput) -> Image {
+        let (width, height) = (aovs.color.width(), aovs.color.height());
+        let normals = aovs.normal.pixels();
+        let albedos = aovs.albedo.pixels();
+        let divisors: Vec<Color> = albedos.iter().map(divisor).collect();
+
+        let mut current: Vec<Color> = aovs.color.pixels().iter()
+            .zip(&divisors)
+            .map(|(color, divisor)| Color::new([0, 1, 2].map(|i| color[i] / divisor[i])))
+            .collect();
+        let mut next = current.clone();
+        let mut color_sigma = self.color_sigma;
+        for i in 0..self.iterations.min(16) {
+            let step = 1usize << i;
+            let color_falloff = falloff(color_sigma);
+            let normal_falloff = falloff(self.normal_sigma);
+            let albedo_falloff = falloff(self.albedo_sigma);
+            for y in 0..height {
+                for x in 0..width {
+                    let p = y * width + x;
+                    let mut sum = Color::new([0.0; 3]);
+                    let mut total = 0.0;
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        let Some(qy) = tap(y, dy, step, height) else { continue };
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let Some(qx) = tap(x, dx, step, width) else { continue };
+                            let q = qy * width + qx;
+                            let weight = ky * kx
+                                * color_falloff((current[p] - current[q]).length_squared())
+                                * normal_falloff((normals[p] - normals[q]).length_squared())
+                                * albedo_falloff((albedos[p] - albedos[q]).length_squared());
+                            sum += weight * current[q];
+                            total += weight;
+                        }
+                    }
+                    // the center tap always has weight, so total > 0
+                    next[p] = sum / total;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            color_sigma /= 2.0;
+        }
+
+        let pixels = current.iter().zip(&divisors).map(|(c, divisor)| *c * *divisor).collect();
+        Image::new(width, height, pixels)
+    }
+}
+
+impl RenderOutput {
+    /// The color buffer filtered by a default `Denoiser`.
+    pub fn denoised(&self) -> Image {
+        Denoiser::new().denoise(self)
+    }
+}
+
+/// Gaussian weight of a squared difference; a non-positive sigma turns
+/// that guide off.
+fn falloff(sigma: f64) -> impl Fn(f64) -> f64 {
+    let scale = if sigma > 0.0 { 1.0 / (sigma * sigma) } else { 0.0 };
+    move |distance_squared| (-distance_squared * scale).exp()
+}
+
+/// Coordinate of kernel tap `k` around `center`, if inside `0..len`.
+fn tap(center: usize, k: usize, step: usize, len: usize) -> Option<usize> {
+    let q = (center + k * step).checked_sub(2 * step)?;
+    (q < len).then_some(q)
+}
+
+fn divisor(albedo: &Color) -> Color {
+    let mut divisor = WHITE;
+    for i in 0..3 {
+        if albedo[i] > MIN_ALBEDO {
+            divisor[i] = albedo[i];
+        }
+    }
+    divisor
+}
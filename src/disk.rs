@@ -0,0 +1,114 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::onb::{Onb};
+use std::f64::consts::PI;
+use crate::aabb::{Aabb, AABB_PADDING};
+use crate::world::{INF};
+use crate::random::random_f64;
+
+/// Flat disk of `radius` around `center`, facing along `normal`; a round
+/// area light when marked as one. Hits report the angle around the
+/// center as `u` in `[0, 1)` and the distance from it as `v`, with 1 at
+/// the rim.
+pub struct Disk {
+    center: Point,
+    // `w` is the unit normal; `u` is where the angle starts
+    basis: Onb,
+    radius: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl Disk {
+    /// The radius must be positive; a flat or negative one has no area to
+    /// sample as a light.
+    pub fn new(center: Point, normal: Vec3, radius: f64, m: Arc<dyn Material>) -> Disk {
+        assert!(radius > 0.0, "disk radius must be positive");
+        Disk {
+            center,
+            basis: Onb::new(&normal),
+            radius,
+            mat: m,
+        }
+    }
+
+    fn normal(&self) -> &Vec3 {
+        self.basis.w()
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Hittable for Disk {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let normal = *self.normal();
+        let denom = normal.dot(ray.direct());
+        // compared relative to the ray's length: grazing rays meet the
+        // plane so far away, or so imprecisely, that the hit is meaningless
+        if denom.abs() <= 1e-9 * ray.direct().length() {
+            return None;
+        }
+
+        let t = normal.dot(&(self.center - *ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let position = ray.range(t);
+        // project back into the plane so rounding in `t` can't push the
+        // radius test either way
+        let offset = position - self.center;
+        let offset = offset - normal.dot(&offset) * normal;
+        let distance = offset.length();
+        if distance > self.radius {
+            return None;
+        }
+
+        let local_x = offset.dot(self.basis.u());
+        let local_y = offset.dot(self.basis.v());
+        let u = (local_y.atan2(local_x) + PI) / (2.0 * PI);
+        let v = if self.radius > 0.0 { distance / self.radius } else { 0.0 };
+        // direction of increasing `u`; any in-plane direction at the center
+        let tangent = if distance > 0.0 { normal.cross(&offset) / distance } else { *self.basis.u() };
+
+        let front_face = denom < 0.0;
+        Some(HitRecord::new(
+            t,
+            self.center + offset,
+            if front_face { normal } else { -normal },
+            front_face,
+            Arc::clone(&self.mat),
+        ).with_uv(u, v).with_tangent(tangent))
+    }
+
+    /// Along each axis the rim reaches `radius * sqrt(1 - normal_i^2)`
+    /// past the center.
+    fn bounding_box(&self) -> Aabb {
+        let normal = *self.normal();
+        let reach = Vec3::new([0, 1, 2].map(|i| self.radius * (1.0 - normal[i] * normal[i]).max(0.0).sqrt()));
+        Aabb::new(self.center - reach, self.center + reach).padded(AABB_PADDING)
+    }
+
+    /// Uniform over the disk's area, converted to solid angle.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        let Some(rec) = self.intersect(&Ray::new(*origin, *direction), t_min, INF) else {
+            return 0.0;
+        };
+        let distance_squared = rec.t() * rec.t() * direction.length_squared();
+        let cosine = (direction.dot(self.normal()) / direction.length()).abs();
+        if cosine < 1e-12 || self.area() <= 0.0 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        // the square root spreads points evenly over the area
+        let r = self.radius * random_f64().sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let p = self.center + self.basis.transform(&Vec3::new([r * phi.cos(), r * phi.sin(), 0.0]));
+        p - *origin
+    }
+}
@@ -0,0 +1,136 @@
+//! Reader for Radiance `.hdr` (RGBE) images, flat or run-length encoded.
+use crate::color::{Color};
+use crate::image::{Image};
+use std::io;
+
+// scanlines between 8 and 0x7fff pixels wide may use the newer RLE scheme
+const RLE_MIN_WIDTH: usize = 8;
+const RLE_MAX_WIDTH: usize = 0x7fff;
+
+/// Decodes a Radiance file into linear colors. Only the standard
+/// `-Y height +X width` orientation is supported.
+pub fn decode(data: &[u8]) -> io::Result<Image> {
+    if !data.starts_with(b"#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+
+    // header lines end at an empty line, followed by the resolution line
+    let mut pos = 0;
+    loop {
+        let line = next_line(data, &mut pos)?;
+        if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("only 32-bit RGBE pixels are supported"));
+        }
+        if line.is_empty() {
+            break;
+        }
+    }
+    let resolution = next_line(data, &mut pos)?;
+    let resolution = std::str::from_utf8(resolution).map_err(|_| invalid("bad resolution line"))?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match fields[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| invalid("bad image height"))?,
+            width.parse::<usize>().map_err(|_| invalid("bad image width"))?,
+        ),
+        _ => return Err(invalid("unsupported image orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("empty HDR image"));
+    }
+    // the header is untrusted: reject sizes the rest of the file can't hold
+    // before allocating for them
+    let fits = min_scanline_len(width)
+        .checked_mul(height)
+        .is_some_and(|len| len <= data.len() - pos);
+    let pixel_count = width.checked_mul(height).filter(|_| fits).ok_or_else(|| invalid("image size exceeds the pixel data"))?;
+
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(data, &mut pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(from_rgbe));
+    }
+    Ok(Image::new(width, height, pixels))
+}
+
+fn next_line<'a>(data: &'a [u8], pos: &mut usize) -> io::Result<&'a [u8]> {
+    let rest = data.get(*pos..).unwrap_or(&[]);
+    let len = rest.iter().position(|&b| b == b'\n').ok_or_else(|| invalid("truncated HDR header"))?;
+    *pos += len + 1;
+    Ok(&rest[..len])
+}
+
+/// Fewest bytes a scanline of `width` pixels can take: flat, or run-length
+/// encoded with every channel in runs of up to 127 pixels.
+fn min_scanline_len(width: usize) -> usize {
+    let flat = width.saturating_mul(4);
+    if (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width) {
+        flat.min(4 + 4 * 2 * width.div_ceil(127))
+    } else {
+        flat
+    }
+}
+
+fn read_scanline(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let head = data.get(*pos..*pos + 4).ok_or_else(|| invalid("not enough pixel data"))?;
+    let is_rle = (RLE_MIN_WIDTH..=RLE_MAX_WIDTH).contains(&width)
+        && head[0] == 2
+        && head[1] == 2
+        && head[2] & 0x80 == 0
+        && ((head[2] as usize) << 8 | head[3] as usize) == width;
+
+    if !is_rle {
+        let flat = data.get(*pos..*pos + width * 4).ok_or_else(|| invalid("not enough pixel data"))?;
+        for (pixel, bytes) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+            pixel.copy_from_slice(bytes);
+        }
+        *pos += width * 4;
+        return Ok(());
+    }
+
+    // each of the four channels is stored separately as runs and literals
+    *pos += 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(*pos).ok_or_else(|| invalid("truncated RLE data"))? as usize;
+            *pos += 1;
+            if count > 128 {
+                let run = count - 128;
+                let value = *data.get(*pos).ok_or_else(|| invalid("truncated RLE data"))?;
+                *pos += 1;
+                if run == 0 || x + run > width {
+                    return Err(invalid("bad RLE run"));
+                }
+                scanline[x..x + run].iter_mut().for_each(|pixel| pixel[channel] = value);
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("bad RLE literal"));
+                }
+                let values = data.get(*pos..*pos + count).ok_or_else(|| invalid("truncated RLE data"))?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                *pos += count;
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new([0.0, 0.0, 0.0]);
+    }
+    // 136 = 128 exponent bias + 8 mantissa bits
+    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+    Color::new([rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
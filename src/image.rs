@@ -0,0 +1,275 @@
+use crate::color::{Color, OutputSettings, to_rgb8, write_color};
+#[cfg(feature = "png")]
+use crate::png;
+use crate::stats::{RenderStats};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Encoding of PPM output: `Ascii` writes `P3` text triples, `Binary`
+/// writes `P6` raw bytes, which is far smaller and faster to serialize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PpmFormat {
+    #[default]
+    Ascii,
+    Binary,
+}
+
+/// File format for a written image. PPM and PNG hold display-encoded
+/// 8-bit values; HDR stores the linear radiance as Radiance RGBE, with no
+/// exposure, tone mapping or gamma, so values above 1.0 survive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ppm(PpmFormat),
+    Png,
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Chooses by extension: `.png`, `.hdr`, and PPM for anything else.
+    pub fn from_path(path: impl AsRef<Path>, ppm_format: PpmFormat) -> OutputFormat {
+        let extension = path.as_ref().extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        if extension.eq_ignore_ascii_case("png") {
+            OutputFormat::Png
+        } else if extension.eq_ignore_ascii_case("hdr") {
+            OutputFormat::Hdr
+        } else {
+            OutputFormat::Ppm(ppm_format)
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Ppm(_) => "ppm",
+            OutputFormat::Png => "png",
+            OutputFormat::Hdr => "hdr",
+        }
+    }
+}
+
+/// A rendered frame: `width * height` linear colors in row-major order,
+/// starting at the top-left pixel.
+#[derive(Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    sample_counts: Option<Vec<u16>>,
+    alpha: Option<Vec<f64>>,
+    stats: Option<RenderStats>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Image {
+        assert_eq!(pixels.len(), width * height, "pixel buffer does not match image size");
+        Image {
+            width,
+            height,
+            pixels,
+            sample_counts: None,
+            alpha: None,
+            stats: None,
+        }
+    }
+
+    /// Builds an image from 8-bit RGB triples, mapping 0..=255 onto 0..=1.
+    pub fn from_rgb8(width: usize, height: usize, data: &[u8]) -> Image {
+        assert_eq!(data.len(), width * height * 3, "pixel data does not match image size");
+        let pixels = data
+            .chunks_exact(3)
+            .map(|p| Color::new([p[0] as f64, p[1] as f64, p[2] as f64]) / 255.0)
+            .collect();
+        Image::new(width, height, pixels)
+    }
+
+    /// Attaches the number of samples taken for every pixel.
+    pub fn with_sample_counts(mut self, sample_counts: Vec<u16>) -> Image {
+        assert_eq!(sample_counts.len(), self.pixels.len(), "sample counts do not match image size");
+        self.sample_counts = Some(sample_counts);
+        self
+    }
+
+    /// Attaches an alpha channel: the coverage of every pixel, by which its
+    /// color is premultiplied.
+    pub fn with_alpha(mut self, alpha: Vec<f64>) -> Image {
+        assert_eq!(alpha.len(), self.pixels.len(), "alpha does not match image size");
+        self.alpha = Some(alpha);
+        self
+    }
+
+    /// Attaches the statistics of the render that made the image.
+    pub fn with_stats(mut self, stats: RenderStats) -> Image {
+        self.stats = Some(stats);
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<Color> {
+        self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Color {
+        &self.pixels[y * self.width + x]
+    }
+
+    /// Coverage of every pixel, when the image has an alpha channel.
+    pub fn alpha(&self) -> Option<&[f64]> {
+        self.alpha.as_deref()
+    }
+
+    /// What the render that made the image did, when it was recorded.
+    pub fn stats(&self) -> Option<&RenderStats> {
+        self.stats.as_ref()
+    }
+
+    /// Samples taken per pixel, when the renderer recorded them.
+    pub fn sample_counts(&self) -> Option<&[u16]> {
+        self.sample_counts.as_deref()
+    }
+
+    /// Averages every `factor` x `factor` block of pixels into one (a box
+    /// filter). Rows and columns left over at the right and bottom edges
+    /// are dropped. Sample counts are summed, alpha averaged and render
+    /// statistics kept.
+    pub fn downsample(&self, factor: usize) -> Image {
+        let factor = factor.max(1);
+        if factor == 1 {
+            return self.clone();
+        }
+        let (width, height) = (self.width / factor, self.height / factor);
+        let block = |x: usize, y: usize| {
+            (0..factor).flat_map(move |dy| (0..factor).map(move |dx| (y * factor + dy) * self.width + x * factor + dx))
+        };
+        let scale = 1.0 / (factor * factor) as f64;
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut sample_counts = Vec::with_capacity(width * height);
+        let mut alpha = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let sum = block(x, y).fold(Color::new([0.0; 3]), |sum, i| sum + self.pixels[i]);
+                pixels.push(sum * scale);
+                if let Some(counts) = &self.sample_counts {
+                    sample_counts.push(block(x, y).fold(0u16, |sum, i| sum.saturating_add(counts[i])));
+                }
+                if let Some(coverage) = &self.alpha {
+                    alpha.push(block(x, y).map(|i| coverage[i]).sum::<f64>() * scale);
+                }
+            }
+        }
+        let mut image = Image::new(width, height, pixels);
+        if self.sample_counts.is_some() {
+            image = image.with_sample_counts(sample_counts);
+        }
+        if self.alpha.is_some() {
+            image = image.with_alpha(alpha);
+        }
+        image.stats = self.stats;
+        image
+    }
+
+    /// Grayscale debug view of `sample_counts`: white pixels took the most samples.
+    pub fn sample_heatmap(&self) -> Option<Image> {
+        let counts = self.sample_counts.as_ref()?;
+        let max = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let pixels = counts.iter()
+            .map(|&n| {
+                let level = n as f64 / max;
+                Color::new([level, level, level])
+            })
+            .collect();
+        Some(Image::new(self.width, self.height, pixels))
+    }
+
+    /// Serializes the image in `format`; `settings` does not apply to HDR.
+    /// PNG fails with `Unsupported` when the `png` feature is disabled.
+    pub fn write(&self, out: &mut impl Write, format: OutputFormat, settings: &OutputSettings) -> io::Result<()> {
+        match format {
+            OutputFormat::Ppm(ppm_format) => self.write_ppm(out, ppm_format, settings),
+            #[cfg(feature = "png")]
+            OutputFormat::Png => self.write_png(out, settings),
+            #[cfg(not(feature = "png"))]
+            OutputFormat::Png => Err(io::Error::new(io::ErrorKind::Unsupported, "PNG support is disabled")),
+            OutputFormat::Hdr => self.write_hdr(out),
+        }
+    }
+
+    pub fn write_ppm(&self, out: &mut impl Write, format: PpmFormat, settings: &OutputSettings) -> io::Result<()> {
+        match format {
+            PpmFormat::Ascii => {
+                let header = format!("P3\n{} {}\n255\n", self.width, self.height);
+                out.write_all(header.as_bytes())?;
+                for color in self.pixels.iter() {
+                    write_color(out, color, settings)?;
+                }
+            },
+            PpmFormat::Binary => {
+                let header = format!("P6\n{} {}\n255\n", self.width, self.height);
+                out.write_all(header.as_bytes())?;
+                let data: Vec<u8> = self.pixels.iter().flat_map(|c| to_rgb8(c, settings)).collect();
+                out.write_all(&data)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Serializes the image as an 8-bit PNG: RGB, or RGBA with straight
+    /// (not premultiplied) color if the image has an alpha channel.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, out: &mut impl Write, settings: &OutputSettings) -> io::Result<()> {
+        let Some(alpha) = &self.alpha else {
+            let data: Vec<u8> = self.pixels.iter().flat_map(|c| to_rgb8(c, settings)).collect();
+            return png::encode(out, self.width, self.height, png::COLOR_TYPE_RGB, &data);
+        };
+        let data: Vec<u8> = self.pixels.iter().zip(alpha)
+            .flat_map(|(c, &a)| {
+                let a = a.clamp(0.0, 1.0);
+                let straight = if a > 0.0 { *c / a } else { *c };
+                let [r, g, b] = to_rgb8(&straight, settings);
+                [r, g, b, (a * 255.0).round() as u8]
+            })
+            .collect();
+        png::encode(out, self.width, self.height, png::COLOR_TYPE_RGBA, &data)
+    }
+
+    /// Serializes the linear image as a Radiance `.hdr` file with flat
+    /// (uncompressed) RGBE scanlines. Negative components are clamped to 0.
+    pub fn write_hdr(&self, out: &mut impl Write) -> io::Result<()> {
+        let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width);
+        out.write_all(header.as_bytes())?;
+        let data: Vec<u8> = self.pixels.iter().flat_map(to_rgbe).collect();
+        out.write_all(&data)
+    }
+}
+
+/// Shared-exponent encoding: three 8-bit mantissas scaled by a common
+/// power of two taken from the largest component.
+fn to_rgbe(c: &Color) -> [u8; 4] {
+    let rgb = [c.x().max(0.0), c.y().max(0.0), c.z().max(0.0)];
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max < 1e-32 || !max.is_finite() {
+        return [0; 4];
+    }
+    // max = m * 2^e with m in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / 2f64.powi(exponent);
+    [
+        (rgb[0] * scale).min(255.0) as u8,
+        (rgb[1] * scale).min(255.0) as u8,
+        (rgb[2] * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
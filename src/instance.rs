@@ -0,0 +1,166 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::material::{Material};
+use std::sync::{Arc};
+
+/// Places `object` shifted by `offset`.
+pub struct Translate {
+    object: Arc<dyn Hittable>,
+    offset: Vec3,
+    bbox: Aabb,
+}
+
+impl Translate {
+    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Translate {
+        let inner = object.bounding_box();
+        let bbox = if inner.is_empty() {
+            inner
+        } else {
+            Aabb::new(*inner.min() + offset, *inner.max() + offset)
+        };
+        Translate {
+            object,
+            offset,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Translate {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = Ray::with_time(*ray.org() - self.offset, *ray.direct(), ray.time());
+        let rec = self.object.intersect(&local, t_min, t_max)?;
+        let pos = *rec.pos() + self.offset;
+        let normal = *rec.normal();
+        Some(rec.with_geometry(pos, normal))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        self.object.pdf_value(&(*origin - self.offset), direction, t_min)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.object.random(&(*origin - self.offset))
+    }
+}
+
+/// Rotates `object` by `angle` degrees about the y axis, counterclockwise
+/// when seen from above.
+pub struct RotateY {
+    object: Arc<dyn Hittable>,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Aabb,
+}
+
+impl RotateY {
+    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> RotateY {
+        let theta = angle.to_radians();
+        let mut rotate = RotateY {
+            object,
+            sin_theta: theta.sin(),
+            cos_theta: theta.cos(),
+            bbox: Aabb::EMPTY,
+        };
+
+        // enclose the rotated corners of the object's box
+        let inner = rotate.object.bounding_box();
+        rotate.bbox = if inner.is_empty() || inner == Aabb::UNIVERSE {
+            inner
+        } else {
+            let mut bbox = Aabb::EMPTY;
+            for corner in 0..8 {
+                let pick = |axis: usize| if corner >> axis & 1 == 1 { inner.max()[axis] } else { inner.min()[axis] };
+                let p = rotate.to_world(&Point::new([pick(0), pick(1), pick(2)]));
+                bbox = bbox.surrounding(&Aabb::new(p, p));
+            }
+            bbox
+        };
+        rotate
+    }
+
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new([
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        ])
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new([
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        ])
+    }
+}
+
+impl Hittable for RotateY {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = Ray::with_time(self.to_object(ray.org()), self.to_object(ray.direct()), ray.time());
+        let rec = self.object.intersect(&local, t_min, t_max)?;
+        let pos = self.to_world(rec.pos());
+        let normal = self.to_world(rec.normal());
+        let tangent = rec.tangent().map(|tangent| self.to_world(tangent));
+        let rec = rec.with_geometry(pos, normal);
+        Some(match tangent {
+            Some(tangent) => rec.with_tangent(tangent),
+            None => rec,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // rotations preserve solid angles, so densities carry over unchanged
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        self.object.pdf_value(&self.to_object(origin), &self.to_object(direction), t_min)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.to_world(&self.object.random(&self.to_object(origin)))
+    }
+}
+
+/// Draws `object` with `material` in place of its own, so shared geometry
+/// such as a loaded mesh can be reused with a different look.
+pub struct WithMaterial {
+    object: Arc<dyn Hittable>,
+    material: Arc<dyn Material>,
+}
+
+impl WithMaterial {
+    pub fn new(object: Arc<dyn Hittable>, material: Arc<dyn Material>) -> WithMaterial {
+        WithMaterial {
+            object,
+            material,
+        }
+    }
+}
+
+impl Hittable for WithMaterial {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let rec = self.object.intersect(ray, t_min, t_max)?;
+        Some(rec.with_material(self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    // the shape is unchanged, so it samples as a light like the original
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        self.object.pdf_value(origin, direction, t_min)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.object.random(origin)
+    }
+}
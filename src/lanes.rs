@@ -0,0 +1,119 @@
+//! Storage and arithmetic for the three components of a `Vec3`: a plain
+//! array by default, or a single 4-lane `std::simd` vector with the `simd`
+//! feature (nightly only). The spare fourth lane always holds 0, so it
+//! drops out of sums such as the dot product.
+
+#[cfg(not(feature = "simd"))]
+mod imp {
+    pub type Lanes = [f64; 3];
+
+    pub const fn new(e: [f64; 3]) -> Lanes {
+        e
+    }
+
+    pub fn components(l: &Lanes) -> &[f64] {
+        l
+    }
+
+    pub fn components_mut(l: &mut Lanes) -> &mut [f64] {
+        l
+    }
+
+    fn map(a: Lanes, b: Lanes, f: impl Fn(f64, f64) -> f64) -> Lanes {
+        [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])]
+    }
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        map(a, b, |x, y| x + y)
+    }
+
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        map(a, b, |x, y| x - y)
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        map(a, b, |x, y| x * y)
+    }
+
+    pub fn offset(a: Lanes, s: f64) -> Lanes {
+        add(a, [s; 3])
+    }
+
+    pub fn scale(a: Lanes, s: f64) -> Lanes {
+        mul(a, [s; 3])
+    }
+
+    pub fn neg(a: Lanes) -> Lanes {
+        [-a[0], -a[1], -a[2]]
+    }
+
+    pub fn dot(a: Lanes, b: Lanes) -> f64 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    pub fn cross(a: Lanes, b: Lanes) -> Lanes {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+}
+
+#[cfg(feature = "simd")]
+mod imp {
+    use std::simd::{f64x4, simd_swizzle};
+    use std::simd::num::SimdFloat;
+
+    pub type Lanes = f64x4;
+
+    pub const fn new(e: [f64; 3]) -> Lanes {
+        f64x4::from_array([e[0], e[1], e[2], 0.0])
+    }
+
+    pub fn components(l: &Lanes) -> &[f64] {
+        &l.as_array()[..3]
+    }
+
+    pub fn components_mut(l: &mut Lanes) -> &mut [f64] {
+        &mut l.as_mut_array()[..3]
+    }
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        a + b
+    }
+
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        a - b
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        a * b
+    }
+
+    pub fn offset(a: Lanes, s: f64) -> Lanes {
+        a + new([s; 3])
+    }
+
+    // scaling by a splat would turn the spare lane into NaN for infinite `s`
+    pub fn scale(a: Lanes, s: f64) -> Lanes {
+        a * new([s; 3])
+    }
+
+    pub fn neg(a: Lanes) -> Lanes {
+        -a
+    }
+
+    pub fn dot(a: Lanes, b: Lanes) -> f64 {
+        (a * b).reduce_sum()
+    }
+
+    pub fn cross(a: Lanes, b: Lanes) -> Lanes {
+        let a_yzx = simd_swizzle!(a, [1, 2, 0, 3]);
+        let b_yzx = simd_swizzle!(b, [1, 2, 0, 3]);
+        let c = a * b_yzx - a_yzx * b;
+        simd_swizzle!(c, [1, 2, 0, 3])
+    }
+}
+
+pub use imp::*;
@@ -1,19 +1,107 @@
 
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+mod random;
+
+mod stats;
+pub use stats::{RenderStats};
+
+mod lanes;
+
 mod vec3;
-pub use vec3::{Point};
+pub use vec3::{Point, Vec3};
+
+mod onb;
+pub use onb::{Onb};
 
 mod ray;
+pub use ray::{Ray, HitRecord, Hittable};
+
+mod aabb;
+pub use aabb::{Aabb};
+
+mod bvh;
+pub use bvh::{Bvh};
+
 mod color;
-pub use color::{Color};
+pub use color::{Color, OutputSettings, ToneMap, TraceSettings, clamp, luminance, ray_color};
+
+mod perlin;
+
+mod texture;
+pub use texture::{Texture, SolidColor, CheckerTexture, ImageTexture, NoiseTexture, TextureError};
+
+mod background;
+pub use background::{Background, EnvMap};
 
 mod sphere;
-pub use sphere::{Sphere};
+pub use sphere::{Sphere, MovingSphere};
+
+mod plane;
+pub use plane::{Plane};
+
+mod quad;
+pub use quad::{Quad};
+
+mod box_prim;
+pub use box_prim::{BoxPrim};
+
+mod cylinder;
+pub use cylinder::{Cylinder};
+
+mod disk;
+pub use disk::{Disk};
+
+mod triangle;
+pub use triangle::{Triangle};
+
+mod mesh;
+pub use mesh::{Mesh, ObjError, load_obj, parse_obj};
+
+mod instance;
+pub use instance::{Translate, RotateY, WithMaterial};
+
+mod medium;
+pub use medium::{ConstantMedium};
 
 mod world;
-pub use world::{World, INF, ORIGIN};
+pub use world::{World, HittableList, INF, ORIGIN};
+
+mod scene;
+pub use scene::{Scene, SceneBuilder, reference_scene};
+
+mod json;
+
+mod scene_file;
+pub use scene_file::{SceneError, load_scene, parse_scene};
+
+mod config;
+pub use config::{RenderConfig};
+
+#[cfg(feature = "png")]
+mod inflate;
+#[cfg(feature = "png")]
+mod png;
+
+mod hdr;
+
+mod image;
+pub use image::{Image, OutputFormat, PpmFormat};
+
+mod checkpoint;
 
 mod camera;
-pub use camera::Camera;
+pub use camera::{Aperture, Camera, CameraBuilder, PixelFilter, ProgressCallback, Projection, RenderError, RenderOutcome, RenderOutput, SamplingStrategy};
+
+#[cfg(feature = "denoise")]
+mod denoise;
+#[cfg(feature = "denoise")]
+pub use denoise::{Denoiser};
+
+mod animation;
+pub use animation::{Frame, render_animation};
+
+mod spectrum;
 
 mod material;
-pub use material::{Material};
\ No newline at end of file
+pub use material::{Material, Lambertian, Metal, Coated, Dielectric, DiffuseLight, Isotropic, NormalMapped};
\ No newline at end of file
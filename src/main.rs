@@ -1,11 +1,138 @@
-use lib::{Material, Camera, World, Sphere, Point, Color, ORIGIN};
+use lib::{Material, Lambertian, Metal, Dielectric, Camera, CameraBuilder, Scene, World, Sphere, Point, Color, ORIGIN, OutputFormat, PpmFormat, load_scene};
+use std::io::{self, BufWriter};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use rand::Rng;
 
+const USAGE: &str = "usage: ray-tracing [options]\n\
+    \n\
+    --scene <file.json>   render a scene file instead of the built-in demo\n\
+    -o, --output <path>   output file (default out.ppm); - writes a binary PPM to stdout\n\
+    --width <pixels>      image width; the height follows the aspect ratio\n\
+    --samples <n>         samples per pixel\n\
+    --depth <n>           maximum bounces per path\n\
+    --threads <n>         worker threads (default: all cores)\n\
+    --preview <n>         trace only every nth row, repeating it below, for a quick look\n\
+    --time <seconds>      sample for this long instead of a fixed sample count\n\
+    -q, --quiet           no progress output\n\
+    --denoise             filter the render (needs the denoise feature)\n\
+    -h, --help            print this message\n\
+    \n\
+    Options given on the command line override the scene file.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// The value following `flag`, parsed as a `T`.
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let arg = args.next().unwrap_or_else(|| usage());
+    arg.parse().unwrap_or_else(|_| {
+        eprintln!("invalid value for {}: {}", flag, arg);
+        usage()
+    })
+}
+
 fn main() {
+    let mut scene_path: Option<String> = None;
+    let mut output = String::from("out.ppm");
+    let mut quiet = false;
+    let mut denoise = false;
+    let mut width: Option<u32> = None;
+    let mut samples: Option<u16> = None;
+    let mut depth: Option<u8> = None;
+    let mut threads: Option<usize> = None;
+    let mut preview: Option<usize> = None;
+    let mut time_budget: Option<f64> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            },
+            "--quiet" | "-q" => quiet = true,
+            "--denoise" => denoise = true,
+            "--scene" => scene_path = Some(value(&mut args, &arg)),
+            "--output" | "-o" => output = value(&mut args, &arg),
+            "--width" => width = Some(value(&mut args, &arg)),
+            "--samples" => samples = Some(value(&mut args, &arg)),
+            "--depth" => depth = Some(value(&mut args, &arg)),
+            "--threads" => threads = Some(value(&mut args, &arg)),
+            "--preview" => preview = Some(value(&mut args, &arg)),
+            "--time" => time_budget = Some(value(&mut args, &arg)),
+            _ => usage(),
+        }
+    }
+
+    let (camera, scene) = match scene_path {
+        None => demo_scene(),
+        Some(path) => match load_scene(&path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("Could not load {}: {}", path, e);
+                std::process::exit(1);
+            },
+        },
+    };
+    let mut camera = camera.quiet(quiet);
+    if let Some(width) = width {
+        camera = camera.width(width);
+    }
+    if let Some(samples) = samples {
+        camera = camera.samples(samples);
+    }
+    if let Some(depth) = depth {
+        camera = camera.max_depth(depth);
+    }
+    if let Some(threads) = threads {
+        camera = camera.threads(threads);
+    }
+    if let Some(stride) = preview {
+        camera = camera.preview_stride(stride);
+    }
+    if let Some(seconds) = time_budget {
+        let Ok(budget) = Duration::try_from_secs_f64(seconds) else {
+            eprintln!("invalid value for --time: {}", seconds);
+            usage()
+        };
+        camera = camera.time_budget(budget);
+    }
+    let camera = with_denoiser(camera, denoise).build();
+
+    let result = if output == "-" {
+        let stdout = BufWriter::new(io::stdout().lock());
+        camera.render_to_writer(&scene, stdout, OutputFormat::Ppm(PpmFormat::Binary))
+    } else {
+        camera.render(&scene, &output)
+    };
+    if let Err(e) = result {
+        eprintln!("Render failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "denoise")]
+fn with_denoiser(camera: CameraBuilder, denoise: bool) -> CameraBuilder {
+    if denoise { camera.denoise(lib::Denoiser::new()) } else { camera }
+}
+
+#[cfg(not(feature = "denoise"))]
+fn with_denoiser(camera: CameraBuilder, denoise: bool) -> CameraBuilder {
+    if denoise {
+        eprintln!("--denoise needs a build with the denoise feature");
+        std::process::exit(2);
+    }
+    camera
+}
+
+/// The final scene of the first book: a field of random small spheres.
+fn demo_scene() -> (CameraBuilder, Scene) {
     let mut world = World::new();
 
-    let material_ground = Material::Lambertian(Color::new([0.5, 0.5, 0.5]));
+    let material_ground = Arc::new(Lambertian::new(Color::new([0.5, 0.5, 0.5])));
     let earth = Sphere::new(Point::new([0.0, -1000.0, 0.0]), 1000.0, material_ground);
     world.add(Arc::new(earth));
 
@@ -20,16 +147,16 @@ fn main() {
                 j as f64 + 0.9 * rng.gen_range(0.0..1.0)
             ]);
 
-            if (center - Point::new([4.0, radius, 0.0])).length() > 0.9 {
-                let sphere_mat = if choose_mat < 0.3 {
+            if (center - Point::new([4.0, radius, 0.0])).length_squared() > 0.9 * 0.9 {
+                let sphere_mat: Arc<dyn Material> = if choose_mat < 0.3 {
                     let albedo = Color::random(0.0, 0.6);
-                    Material::Lambertian(albedo)
+                    Arc::new(Lambertian::new(albedo))
                 } else if choose_mat < 0.9 {
                     let albedo = Color::random(0.5, 1.0);
                     let fuzz = rng.gen_range(0.0..0.4);
-                    Material::Metal(albedo, fuzz)
+                    Arc::new(Metal::new(albedo, fuzz))
                 } else {
-                    Material::Dielectric(rng.gen_range(0.5..2.0))
+                    Arc::new(Dielectric::new(rng.gen_range(0.5..2.0)))
                 };
 
                 world.add(Arc::new(Sphere::new(center, radius, sphere_mat)));
@@ -37,19 +164,21 @@ fn main() {
         }
     }
 
-    let material_big_ball_1 = Material::Lambertian(Color::new([0.8, 0.65, 0.3]));
+    let material_big_ball_1 = Arc::new(Lambertian::new(Color::new([0.8, 0.65, 0.3])));
     let big_ball_1 = Sphere::new(Point::new([-150.0, 69.0, -30.0]), 80.0, material_big_ball_1);
     world.add(Arc::new(big_ball_1));
     
-    let material_big_ball_2 = Material::Dielectric(1.5);
+    let material_big_ball_2 = Arc::new(Dielectric::new(1.5));
     let big_ball_2 = Sphere::new(Point::new([-4.0, 1.0, 0.0]), 1.0, material_big_ball_2);
     world.add(Arc::new(big_ball_2));
 
-    let material_big_ball_3 = Material::Metal(Color::new([0.5, 0.6, 0.7]), 0.0);
+    let material_big_ball_3 = Arc::new(Metal::new(Color::new([0.5, 0.6, 0.7]), 0.0));
     let big_ball_3 = Sphere::new(Point::new([4.0, 1.0, 0.0]), 1.0, material_big_ball_3);
     world.add(Arc::new(big_ball_3));
 
-    let c = Camera::new(Point::new([13.0, 2.0, 3.0]), ORIGIN);
-    c.render(Arc::new(world));
+    let camera = Camera::builder()
+        .look_from(Point::new([13.0, 2.0, 3.0]))
+        .look_at(ORIGIN);
+    (camera, Scene::new(Arc::new(world)))
 }
 
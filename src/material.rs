@@ -1,82 +1,498 @@
+use std::borrow::Cow;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
 use crate::ray::{Ray, HitRecord};
 use crate::vec3::{Vec3};
-use crate::color::{Color};
-use rand::Rng;
-
-
-#[derive(PartialEq, Clone, Copy)]
-pub enum Material {
-    Lambertian(Color),
-    Metal(Color, f64),
-    Dielectric(f64),
-}
-
-pub fn scatter(mat: &Material, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-    match mat {
-        Material::Lambertian(albedo) => {
-            if let Some(ray) = lambertian_scatter(rec) {
-                return Some((ray, *albedo));
-            }
-        },
-        Material::Metal(albedo, fuzz) => {
-            if let Some(ray) = metal_scatter(incident, rec, fuzz) {
-                return Some((ray, *albedo));
-            }
-        },
-        Material::Dielectric(refractive_index) => {
-            if let Some(ray) = dielectrics_scatter(incident, rec, refractive_index) {
-                return Some((ray, Color::new([1.0, 1.0, 1.0])));
-            }
-        },
+use crate::onb::{Onb};
+use crate::color::{Color, BLACK, WHITE};
+use crate::random::random_f64;
+use crate::texture::{Texture, SolidColor};
+use crate::spectrum;
+
+// finite difference step of bump mapping, in both uv and world units
+const BUMP_STEP: f64 = 1e-4;
+
+
+/// Surface response to light. `ray_color` asks the material of every hit
+/// for the scattered ray (with its attenuation) and for emitted light.
+pub trait Material: Send + Sync {
+    /// Continues the path after a hit, or `None` if the ray is absorbed.
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+
+    /// Radiance given off by the surface at the hit.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        BLACK
+    }
+
+    /// Base color at the hit, written to the albedo output buffer. Clear
+    /// and unknown materials report white.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        WHITE
+    }
+
+    /// Solid-angle density with which `scatter` picks the direction of
+    /// `scattered`. Diffuse materials report it so that light sampling can
+    /// weigh shadow rays against it; specular ones keep the default of 0
+    /// and are never light sampled.
+    fn scattering_pdf(&self, _incident: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+
+    /// `scatter` together with the `scattering_pdf` of the scattered ray,
+    /// or 0 if this particular bounce was specular. Materials that pick
+    /// between a diffuse and a specular lobe override it, since the
+    /// direction alone can't tell which one was taken.
+    fn scatter_with_pdf(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f64)> {
+        let (scattered, attenuation) = self.scatter(incident, rec)?;
+        let pdf = self.scattering_pdf(incident, rec, &scattered);
+        Some((scattered, attenuation, pdf))
     }
-    None
 }
 
+/// Wraps `material` and bends its shading normal with a tangent-space
+/// normal map, for surface detail without extra geometry. Map colors
+/// encode directions as `2c - 1`: red along the surface tangent (`u`),
+/// green along the bitangent (`v`), blue along the normal, so flat
+/// `(0.5, 0.5, 1)` leaves the surface unchanged. Hits on shapes that
+/// provide no tangent keep the geometric normal.
+pub struct NormalMapped {
+    material: Arc<dyn Material>,
+    normal_map: Arc<dyn Texture>,
+}
 
-fn lambertian_scatter(rec: &HitRecord) -> Option<Ray> {
-    let mut scatter_direction = *rec.normal() + Vec3::random_unit_vec();
-    if scatter_direction.near_zero() {
-        scatter_direction = *rec.normal();
+impl NormalMapped {
+    pub fn new(material: Arc<dyn Material>, normal_map: Arc<dyn Texture>) -> NormalMapped {
+        NormalMapped { material, normal_map }
     }
-    
+
+    /// `rec` with its normal replaced by the mapped one.
+    fn shade(&self, rec: &HitRecord) -> HitRecord {
+        let Some((tangent, bitangent, normal)) = tangent_frame(rec) else {
+            return rec.clone();
+        };
+        let c = self.normal_map.value(rec.u(), rec.v(), rec.pos());
+        let mapped = (2.0 * c.x() - 1.0) * tangent + (2.0 * c.y() - 1.0) * bitangent + (2.0 * c.z() - 1.0) * normal;
+        with_shading_normal(rec, mapped)
+    }
+}
+
+/// Height texture and scale of a bump-mapped surface.
+struct Bump {
+    height: Arc<dyn Texture>,
+    strength: f64,
+}
+
+impl Bump {
+    /// `rec` with its normal tilted down the slope of the height field,
+    /// estimated by forward differences along the tangent and bitangent.
+    /// Each step moves `u`/`v` and the position together, so both image
+    /// and solid (position-based) textures work.
+    fn shade(&self, rec: &HitRecord) -> HitRecord {
+        let Some((tangent, bitangent, normal)) = tangent_frame(rec) else {
+            return rec.clone();
+        };
+        let height = |du: f64, dv: f64| {
+            let p = *rec.pos() + du * tangent + dv * bitangent;
+            let c = self.height.value(rec.u() + du, rec.v() + dv, &p);
+            (c.x() + c.y() + c.z()) / 3.0
+        };
+        let h = height(0.0, 0.0);
+        let slope_u = (height(BUMP_STEP, 0.0) - h) / BUMP_STEP;
+        let slope_v = (height(0.0, BUMP_STEP) - h) / BUMP_STEP;
+        with_shading_normal(rec, normal - self.strength * (slope_u * tangent + slope_v * bitangent))
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.material.scatter(incident, &self.shade(rec))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn scattering_pdf(&self, incident: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(incident, &self.shade(rec), scattered)
+    }
+
+    fn scatter_with_pdf(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f64)> {
+        self.material.scatter_with_pdf(incident, &self.shade(rec))
+    }
+}
+
+/// Ideal diffuse surface: scatters around the normal with a cosine
+/// distribution and attenuates by the albedo.
+pub struct Lambertian {
+    albedo: Arc<dyn Texture>,
+    bump: Option<Bump>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    /// Albedo looked up from a texture at every hit.
+    pub fn textured(albedo: Arc<dyn Texture>) -> Lambertian {
+        Lambertian { albedo, bump: None }
+    }
+
+    /// Diffuse surface whose shading normal follows the gray level of
+    /// `height` (e.g. a `NoiseTexture`) as if it were a height field,
+    /// scaled by `strength`. A cheap alternative to a `NormalMapped`
+    /// material; only shapes that supply tangents are affected.
+    pub fn with_bump(albedo: Color, height: Arc<dyn Texture>, strength: f64) -> Lambertian {
+        Lambertian {
+            bump: Some(Bump { height, strength }),
+            ..Lambertian::new(albedo)
+        }
+    }
+
+    fn shade<'a>(&self, rec: &'a HitRecord) -> Cow<'a, HitRecord> {
+        match &self.bump {
+            Some(bump) => Cow::Owned(bump.shade(rec)),
+            None => Cow::Borrowed(rec),
+        }
+    }
+}
+
+impl Default for Lambertian {
+    /// Mid-gray, the renderer's default surface.
+    fn default() -> Lambertian {
+        Lambertian::new(Color::new([0.5, 0.5, 0.5]))
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        lambertian_scatter(incident, &self.shade(rec)).map(|ray| (ray, albedo_at(&*self.albedo, rec)))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        albedo_at(&*self.albedo, rec)
+    }
+
+    /// Cosine-weighted about the normal.
+    fn scattering_pdf(&self, _incident: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = self.shade(rec).normal().dot(&scattered.direct().unit());
+        (cosine / PI).max(0.0)
+    }
+}
+
+/// Mirror reflection tinted by the albedo; the fuzz in `[0, 1]` blurs
+/// the reflection by jittering it inside a sphere of that radius.
+pub struct Metal {
+    albedo: Arc<dyn Texture>,
+    fuzz: f64,
+    /// Roughness along the tangent and bitangent, replacing the fuzz.
+    anisotropy: Option<(f64, f64)>,
+}
+
+impl Metal {
+    /// The fuzz is clamped to `[0, 1]`.
+    pub fn new(albedo: Color, fuzz: f64) -> Metal {
+        Metal::textured(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    /// Tint looked up from a texture at every hit.
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Metal {
+        Metal {
+            albedo,
+            fuzz: fuzz.clamp(0.0, 1.0),
+            anisotropy: None,
+        }
+    }
+
+    /// Brushed metal, rough by `rough_u` along the hit's tangent and by
+    /// `rough_v` across it, each clamped to `[0, 1]`. The reflection is
+    /// blurred into an ellipse, stretching highlights across the
+    /// direction of the brush strokes. Shapes that supply no tangent get
+    /// an arbitrary one.
+    pub fn anisotropic(albedo: Color, rough_u: f64, rough_v: f64) -> Metal {
+        Metal {
+            anisotropy: Some((rough_u.clamp(0.0, 1.0), rough_v.clamp(0.0, 1.0))),
+            ..Metal::new(albedo, 0.0)
+        }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let scattered = match self.anisotropy {
+            Some((rough_u, rough_v)) => anisotropic_scatter(incident, rec, rough_u, rough_v),
+            None => metal_scatter(incident, rec, &self.fuzz),
+        };
+        scattered.map(|ray| (ray, albedo_at(&*self.albedo, rec)))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        albedo_at(&*self.albedo, rec)
+    }
+}
+
+/// Plastic or painted surface: a diffuse base under a clear, glossy coat
+/// with refractive index `coat_ior`. Every bounce reflects off the coat
+/// with the Fresnel probability at the viewing angle and otherwise
+/// scatters off the base, so the surface turns mirror-like at grazing
+/// angles, giving the bright rims of plastics. The roughness in `[0, 1]`
+/// blurs the coat like the fuzz of `Metal`.
+pub struct Coated {
+    base_albedo: Arc<dyn Texture>,
+    coat_ior: f64,
+    roughness: f64,
+}
+
+impl Coated {
+    pub fn new(base_albedo: Color, coat_ior: f64, roughness: f64) -> Coated {
+        Coated::textured(Arc::new(SolidColor::new(base_albedo)), coat_ior, roughness)
+    }
+
+    /// Base color looked up from a texture at every hit.
+    pub fn textured(base_albedo: Arc<dyn Texture>, coat_ior: f64, roughness: f64) -> Coated {
+        assert!(coat_ior > 0.0, "refractive index must be positive");
+        Coated {
+            base_albedo,
+            coat_ior,
+            roughness: roughness.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.scatter_with_pdf(incident, rec).map(|(ray, attenuation, _)| (ray, attenuation))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        albedo_at(&*self.base_albedo, rec)
+    }
+
+    /// Cosine-weighted about the normal: the density of the base, as the
+    /// coat is only ever reached by following its reflection.
+    fn scattering_pdf(&self, _incident: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cosine = rec.normal().dot(&scattered.direct().unit());
+        (cosine / PI).max(0.0)
+    }
+
+    fn scatter_with_pdf(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color, f64)> {
+        let cos_theta = rec.normal().dot(&-incident.direct().unit()).clamp(0.0, 1.0);
+        // the pick's probability cancels the Fresnel weight of either lobe
+        if random_f64() < reflectance(cos_theta, self.coat_ior) {
+            return metal_scatter(incident, rec, &self.roughness).map(|ray| (ray, WHITE, 0.0));
+        }
+        let scattered = lambertian_scatter(incident, rec)?;
+        let pdf = self.scattering_pdf(incident, rec, &scattered);
+        Some((scattered, albedo_at(&*self.base_albedo, rec), pdf))
+    }
+}
+
+/// Clear refractive material such as glass (1.5) or water (1.33), given
+/// by its refractive index relative to the surrounding medium. Rays
+/// refract by Snell's law, reflect under total internal reflection and
+/// otherwise choose reflection with the Schlick probability.
+pub struct Dielectric {
+    refractive_index: f64,
+    /// Cauchy coefficients B and C, in square and fourth power micrometers.
+    dispersion: Option<(f64, f64)>,
+}
+
+// sodium D line, where refractive indices are usually quoted
+const D_LINE_MICROMETERS: f64 = 0.5893;
+
+impl Dielectric {
+    pub fn new(refractive_index: f64) -> Dielectric {
+        assert!(refractive_index > 0.0, "refractive index must be positive");
+        Dielectric { refractive_index, dispersion: None }
+    }
+
+    /// Varies the index with wavelength by Cauchy's equation
+    /// `n = A + b / l^2 + c / l^4`, `l` in micrometers, with `A` chosen so
+    /// the index given to `new` holds at 589.3 nm. Crown glass (BK7) has
+    /// `b = 0.0042`, `c = 0`. Every path through the material is split into
+    /// a single random wavelength, so white light fans out into colors;
+    /// expect to need more samples for the colors to converge.
+    pub fn with_dispersion(mut self, b: f64, c: f64) -> Dielectric {
+        self.dispersion = Some((b, c));
+        self
+    }
+
+    fn index_at(&self, nm: f64) -> f64 {
+        let Some((b, c)) = self.dispersion else {
+            return self.refractive_index;
+        };
+        let cauchy = |l: f64| b / (l * l) + c / (l * l * l * l);
+        self.refractive_index - cauchy(D_LINE_MICROMETERS) + cauchy(nm / 1000.0)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        if self.dispersion.is_none() {
+            return dielectrics_scatter(incident, rec, &self.refractive_index).map(|ray| (ray, WHITE));
+        }
+        let (nm, weight) = match incident.wavelength() {
+            Some(nm) => (nm, WHITE),
+            None => spectrum::sample_wavelength(),
+        };
+        dielectrics_scatter(incident, rec, &self.index_at(nm)).map(|ray| (ray.with_wavelength(nm), weight))
+    }
+}
+
+/// Light source: emits the given radiance and scatters nothing.
+/// Components above 1.0 make it bright enough to light a scene.
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+
+    /// Emitter with the color of a blackbody at `kelvin` (see
+    /// `Color::from_kelvin`) and a luminance of `intensity`.
+    pub fn from_kelvin(kelvin: f64, intensity: f64) -> DiffuseLight {
+        DiffuseLight::new(intensity * Color::from_kelvin(kelvin))
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _incident: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        // lights only shine from their front side
+        if rec.front_face() { self.emit } else { BLACK }
+    }
+
+    /// The emitted color scaled so its brightest channel is 1.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        let max = self.emit.x().max(self.emit.y()).max(self.emit.z());
+        if max > 0.0 { self.emit / max } else { BLACK }
+    }
+}
+
+/// Scatters uniformly in all directions regardless of the incoming ray;
+/// the phase function of participating media such as `ConstantMedium`.
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, incident: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let scattered = Ray::with_time(*rec.pos(), Vec3::random_unit_vector(), incident.time());
+        Some((scattered, albedo_at(&*self.albedo, rec)))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        albedo_at(&*self.albedo, rec)
+    }
+
+    fn scattering_pdf(&self, _incident: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Unit tangent, bitangent and normal at the hit, for shapes that supply
+/// a tangent.
+fn tangent_frame(rec: &HitRecord) -> Option<(Vec3, Vec3, Vec3)> {
+    let normal = *rec.normal();
+    // re-orthogonalize in case the tangent is not exactly in the surface
+    let tangent = *rec.tangent()?;
+    let tangent = tangent - normal.dot(&tangent) * normal;
+    if tangent.near_zero() {
+        return None;
+    }
+    let tangent = tangent.unit();
+    Some((tangent, normal.cross(&tangent), normal))
+}
+
+/// `rec` with its shading normal replaced, unless `normal` degenerated.
+fn with_shading_normal(rec: &HitRecord, normal: Vec3) -> HitRecord {
+    if normal.near_zero() {
+        return rec.clone();
+    }
+    rec.clone().with_geometry(*rec.pos(), normal.unit())
+}
+
+fn albedo_at(texture: &dyn Texture, rec: &HitRecord) -> Color {
+    texture.value(rec.u(), rec.v(), rec.pos())
+}
+
+fn lambertian_scatter(ray: &Ray, rec: &HitRecord) -> Option<Ray> {
+    let scatter_direction = Onb::new(rec.normal()).transform(&Vec3::random_cosine_direction());
     Some(
-        Ray::new(*rec.pos(), scatter_direction)
+        Ray::with_time(*rec.pos(), scatter_direction, ray.time())
     )
 }
 
 fn metal_scatter(ray: &Ray, rec: &HitRecord, fuzz: &f64) -> Option<Ray> {
-    let mut scatter_direction = ray.direct().specular(rec.normal());
-    scatter_direction = scatter_direction.unit() + fuzz.min(1.0) * Vec3::random_unit_vec();
+    let mut scatter_direction = ray.direct().reflect(rec.normal());
+    scatter_direction = scatter_direction.unit() + fuzz.clamp(0.0, 1.0) * Vec3::random_in_unit_sphere();
+    // fuzzed below the surface: absorbed
     if scatter_direction.dot(rec.normal()) > 0.0 {
-        return Some(Ray::new(*rec.pos(), scatter_direction));
+        return Some(Ray::with_time(*rec.pos(), scatter_direction, ray.time()));
+    }
+    None
+}
+
+/// Reflects off a microfacet normal tilted by a point of the unit disk,
+/// scaled to an ellipse in tangent space.
+fn anisotropic_scatter(ray: &Ray, rec: &HitRecord, rough_u: f64, rough_v: f64) -> Option<Ray> {
+    let (tangent, bitangent, normal) = tangent_frame(rec).unwrap_or_else(|| {
+        let basis = Onb::new(rec.normal());
+        (*basis.u(), *basis.v(), *basis.w())
+    });
+    let offset = Vec3::random_in_unit_disk();
+    let facet = (normal + rough_u * offset.x() * tangent + rough_v * offset.y() * bitangent).unit();
+    let scatter_direction = ray.direct().unit().reflect(&facet);
+    // reflected below the surface: absorbed
+    if scatter_direction.dot(&normal) > 0.0 {
+        return Some(Ray::with_time(*rec.pos(), scatter_direction, ray.time()));
     }
     None
 }
 
 fn dielectrics_scatter(ray: &Ray, rec: &HitRecord, eta: &f64) -> Option<Ray> {
-    let ri: f64;
-    if rec.front_face() { ri = 1.0 / eta; } 
-    else { ri = *eta; }
+    // entering the medium divides by its index, leaving multiplies
+    let ri = if rec.front_face() { 1.0 / eta } else { *eta };
 
     let ray_direct_unit = ray.direct().unit();
-    let cos_theta = rec.normal().dot(&ray_direct_unit.reverse()).min(1.0);
+    let cos_theta = rec.normal().dot(&-ray_direct_unit).min(1.0);
     let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
     
-    let direction;
+    // beyond the critical angle only total internal reflection is possible
     let cannot_refract = ri * sin_theta > 1.0;
-    let mut rng = rand::thread_rng();
-    let schlick = reflectance(cos_theta, ri) > rng.gen_range(0.0..1.0);
-    if cannot_refract || schlick {
-        direction = ray_direct_unit.specular(rec.normal());
+    let schlick = reflectance(cos_theta, ri) > random_f64();
+    let direction = if cannot_refract || schlick {
+        ray_direct_unit.reflect(rec.normal())
     } else {
-        direction = ray_direct_unit.refract(rec.normal(), ri);
-    }
+        ray_direct_unit.refract(rec.normal(), ri)
+    };
 
-    Some(Ray::new(*rec.pos(), direction))
+    Some(Ray::with_time(*rec.pos(), direction, ray.time()))
 }
 
+/// Schlick's approximation of the Fresnel reflectance.
 fn reflectance(cos: f64, refractive_index: f64) -> f64 {
     let mut r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
     r0 = r0 * r0;
-    r0 + (1.0 - r0) * (1.0 - cos).powf(5.0)
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
 }
\ No newline at end of file
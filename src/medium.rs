@@ -0,0 +1,80 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material, Isotropic};
+use crate::texture::{Texture};
+use crate::color::{Color};
+use crate::vec3::{Vec3};
+use crate::aabb::{Aabb};
+use crate::world::{INF};
+use crate::random::random_f64;
+use std::sync::{Arc};
+
+// gap between the entry hit and the search for the exit hit
+const EXIT_EPSILON: f64 = 1e-4;
+
+/// Fog or smoke of uniform `density` filling a closed `boundary`. A ray
+/// travelling a distance `d` inside scatters with probability
+/// `1 - exp(-density * d)`, in a uniformly random direction tinted by
+/// the phase texture. Any material can stand in for the phase function
+/// via `with_phase`, `Isotropic` being the physical choice.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, phase_texture: Arc<dyn Texture>) -> ConstantMedium {
+        ConstantMedium::with_phase(boundary, density, Arc::new(Isotropic::textured(phase_texture)))
+    }
+
+    /// A medium of a single color.
+    pub fn from_color(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> ConstantMedium {
+        ConstantMedium::with_phase(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn with_phase(boundary: Arc<dyn Hittable>, density: f64, phase: Arc<dyn Material>) -> ConstantMedium {
+        assert!(density > 0.0, "medium density must be positive");
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // find where the whole line enters and leaves the boundary, so rays
+        // starting inside the volume are handled too
+        let entry = self.boundary.intersect(ray, -INF, INF)?;
+        let exit = self.boundary.intersect(ray, entry.t() + EXIT_EPSILON, INF)?;
+
+        let t_enter = entry.t().max(t_min).max(0.0);
+        let t_exit = exit.t().min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.direct().length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        // 1 - random_f64() is in (0, 1], keeping the log finite
+        let hit_distance = self.neg_inv_density * (1.0 - random_f64()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // a volume has no surface; the normal and side are arbitrary
+        Some(HitRecord::new(
+            t,
+            ray.range(t),
+            Vec3::new([1.0, 0.0, 0.0]),
+            true,
+            Arc::clone(&self.phase),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use crate::triangle::{Triangle};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::bvh::{Bvh};
+use std::sync::{Arc};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct Mesh {
+    triangles: Vec<Triangle>,
+}
+
+impl Mesh {
+    pub fn new(triangles: Vec<Triangle>) -> Mesh {
+        Mesh {
+            triangles,
+        }
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    pub fn into_triangles(self) -> Vec<Triangle> {
+        self.triangles
+    }
+
+    /// Moves the triangles into a BVH, which is much faster to intersect
+    /// than the linear scan `Mesh` does on its own.
+    pub fn into_bvh(self) -> Bvh {
+        let objects: Vec<Arc<dyn Hittable>> = self.triangles.into_iter()
+            .map(|triangle| Arc::new(triangle) as Arc<dyn Hittable>)
+            .collect();
+        Bvh::new(&objects)
+    }
+}
+
+impl Hittable for Mesh {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = t_max;
+        let mut result = None;
+
+        for triangle in self.triangles.iter() {
+            if let Some(rec) = triangle.intersect(ray, t_min, closest) {
+                closest = rec.t();
+                result = Some(rec);
+            }
+        }
+
+        result
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.triangles.iter().fold(Aabb::EMPTY, |acc, triangle| acc.surrounding(&triangle.bounding_box()))
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "could not read OBJ file: {}", e),
+            ObjError::Parse { line, message } => write!(f, "OBJ line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Loads the geometry of a Wavefront OBJ file as a triangle mesh with a
+/// single material.
+///
+/// Polygons are fan-triangulated and negative (relative) indices are
+/// resolved. Faces without `vn` normals use their flat face normal.
+/// Texture coordinates, groups and material statements are ignored.
+pub fn load_obj(path: impl AsRef<Path>, mat: Arc<dyn Material>) -> Result<Mesh, ObjError> {
+    let source = fs::read_to_string(path).map_err(ObjError::Io)?;
+    parse_obj(&source, mat)
+}
+
+/// Parses OBJ text; see `load_obj`.
+pub fn parse_obj(source: &str, mat: Arc<dyn Material>) -> Result<Mesh, ObjError> {
+    let mut positions: Vec<Point> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut triangles = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let err = |message: String| ObjError::Parse { line: line_no, message };
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+
+        match fields.next() {
+            Some("v") => positions.push(parse_vec3(fields).map_err(err)?),
+            Some("vn") => normals.push(parse_vec3(fields).map_err(err)?),
+            Some("f") => {
+                let mut corners = Vec::new();
+                for field in fields {
+                    corners.push(parse_corner(field, positions.len(), normals.len()).map_err(err)?);
+                }
+                if corners.len() < 3 {
+                    return Err(err(format!("face needs at least 3 vertices, got {}", corners.len())));
+                }
+
+                for i in 1..corners.len() - 1 {
+                    let [a, b, c] = [corners[0], corners[i], corners[i + 1]];
+                    let triangle = Triangle::new(positions[a.0], positions[b.0], positions[c.0], Arc::clone(&mat));
+                    let triangle = match (a.1, b.1, c.1) {
+                        (Some(na), Some(nb), Some(nc)) => triangle.with_normals(normals[na], normals[nb], normals[nc]),
+                        _ => triangle,
+                    };
+                    triangles.push(triangle);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok(Mesh::new(triangles))
+}
+
+fn parse_vec3<'a>(mut fields: impl Iterator<Item = &'a str>) -> Result<Vec3, String> {
+    let mut e = [0.0; 3];
+    for value in e.iter_mut() {
+        let field = fields.next().ok_or("expected 3 coordinates")?;
+        *value = field.parse().map_err(|_| format!("invalid number '{}'", field))?;
+    }
+    Ok(Vec3::new(e))
+}
+
+/// Parses a face corner (`v`, `v/vt`, `v//vn` or `v/vt/vn`) into
+/// zero-based position and normal indices.
+fn parse_corner(field: &str, position_count: usize, normal_count: usize) -> Result<(usize, Option<usize>), String> {
+    let mut parts = field.split('/');
+    let position = resolve_index(parts.next().unwrap_or(""), position_count)?;
+    let _texture = parts.next();
+    let normal = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normal_count)?),
+        _ => None,
+    };
+    Ok((position, normal))
+}
+
+/// OBJ indices are 1-based; negative ones count back from the latest element.
+fn resolve_index(field: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = field.parse().map_err(|_| format!("invalid index '{}'", field))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range", index));
+    }
+    Ok(resolved as usize)
+}
@@ -0,0 +1,35 @@
+use crate::vec3::{Vec3};
+
+/// Orthonormal basis `(u, v, w)` around a direction `w`, used to sample
+/// directions in a local frame where `w` is the `+z` axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Builds a right-handed basis with `w` along `normal`, which need not
+    /// be normalized. The tangents follow Duff et al.'s branchless
+    /// construction, which stays well conditioned for every normal,
+    /// including those close to a coordinate axis.
+    pub fn new(normal: &Vec3) -> Onb {
+        let w = normal.unit();
+        let sign = 1.0f64.copysign(w.z());
+        let a = -1.0 / (sign + w.z());
+        let b = w.x() * w.y() * a;
+        let u = Vec3::new([1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()]);
+        let v = Vec3::new([b, sign + w.y() * w.y() * a, -w.y()]);
+        Onb { u, v, w }
+    }
+
+    pub fn u(&self) -> &Vec3 { &self.u }
+    pub fn v(&self) -> &Vec3 { &self.v }
+    pub fn w(&self) -> &Vec3 { &self.w }
+
+    /// Converts local coordinates `(a, b, c)` along `(u, v, w)` to world space.
+    pub fn transform(&self, local: &Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+}
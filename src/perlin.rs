@@ -0,0 +1,89 @@
+//! Gradient (Perlin) noise over a 256-cell lattice that repeats along
+//! every axis, so the noise is continuous everywhere and tiles without seams.
+use crate::random::random_range;
+use crate::vec3::{Point, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new() -> Perlin {
+        let gradients = (0..POINT_COUNT).map(|_| Vec3::random(-1.0, 1.0).unit()).collect();
+        Perlin {
+            gradients,
+            perm: [permutation(), permutation(), permutation()],
+        }
+    }
+
+    /// Smooth noise in about `[-1, 1]`, zero at every lattice point.
+    pub fn noise(&self, p: &Point) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let frac = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let cell = floor.map(|f| f as i64);
+
+        let mut corners = [[[Vec3::new([0.0; 3]); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm[0][lattice(cell[0], di)]
+                        ^ self.perm[1][lattice(cell[1], dj)]
+                        ^ self.perm[2][lattice(cell[2], dk)];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        trilinear(&corners, frac)
+    }
+
+    /// Absolute value of the sum of `depth` octaves of signed noise, each
+    /// at twice the frequency and half the weight of the previous one.
+    pub fn turbulence(&self, p: &Point, depth: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(&point);
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+        sum.abs()
+    }
+}
+
+fn lattice(cell: i64, offset: usize) -> usize {
+    (cell + offset as i64).rem_euclid(POINT_COUNT as i64) as usize
+}
+
+fn permutation() -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = (random_range(0.0..(i + 1) as f64) as usize).min(i);
+        perm.swap(i, target);
+    }
+    perm
+}
+
+/// Blends the dot products of the corner gradients with the offsets to
+/// `frac`, using Hermite smoothing so the result has no creases.
+fn trilinear(corners: &[[[Vec3; 2]; 2]; 2], frac: [f64; 3]) -> f64 {
+    let smooth = frac.map(|t| t * t * (3.0 - 2.0 * t));
+    let mut sum = 0.0;
+    for (i, plane) in corners.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, gradient) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let offset = Vec3::new([frac[0] - fi, frac[1] - fj, frac[2] - fk]);
+                sum += (fi * smooth[0] + (1.0 - fi) * (1.0 - smooth[0]))
+                    * (fj * smooth[1] + (1.0 - fj) * (1.0 - smooth[1]))
+                    * (fk * smooth[2] + (1.0 - fk) * (1.0 - smooth[2]))
+                    * gradient.dot(&offset);
+            }
+        }
+    }
+    sum
+}
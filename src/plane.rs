@@ -0,0 +1,56 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+
+/// Infinite plane through `point` perpendicular to `normal`.
+pub struct Plane {
+    point: Point,
+    normal: Vec3,
+    mat: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Point, normal: Vec3, m: Arc<dyn Material>) -> Plane {
+        Plane {
+            point,
+            normal: normal.unit(),
+            mat: m,
+        }
+    }
+
+    /// Horizontal plane at height `y` facing up, e.g. a ground floor.
+    pub fn xz(y: f64, m: Arc<dyn Material>) -> Plane {
+        Plane::new(Point::new([0.0, y, 0.0]), Vec3::new([0.0, 1.0, 0.0]), m)
+    }
+}
+
+impl Hittable for Plane {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal.dot(ray.direct());
+        // parallel rays never meet the plane
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = self.normal.dot(&(self.point - *ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let front_face = denom < 0.0;
+        let normal = if front_face { self.normal } else { -self.normal };
+        Some(HitRecord::new(
+            t,
+            ray.range(t),
+            normal,
+            front_face,
+            Arc::clone(&self.mat),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::UNIVERSE
+    }
+}
@@ -0,0 +1,294 @@
+//! Minimal PNG encoder so the renderer does not depend on external tools.
+//! Image data is stored with zlib "stored" (uncompressed) deflate blocks,
+//! which every decoder understands and keeps the encoder tiny.
+//!
+//! `decode` reads non-interlaced PNGs of any color type for textures.
+use std::io::{self, Write};
+
+use crate::inflate;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const MAX_STORED_BLOCK: usize = 65535;
+
+pub const COLOR_TYPE_GRAY: u8 = 0;
+pub const COLOR_TYPE_RGB: u8 = 2;
+pub const COLOR_TYPE_PALETTE: u8 = 3;
+pub const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+pub const COLOR_TYPE_RGBA: u8 = 6;
+
+/// A decoded image as 8-bit RGB triples in row-major order, top row first.
+pub struct Decoded {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+/// Writes an 8-bit PNG. `data` holds `channels` bytes per pixel in row-major order.
+pub fn encode(out: &mut impl Write, width: usize, height: usize, color_type: u8, data: &[u8]) -> io::Result<()> {
+    let channels = match color_type {
+        COLOR_TYPE_RGB => 3,
+        COLOR_TYPE_RGBA => 4,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported PNG color type")),
+    };
+    assert_eq!(data.len(), width * height * channels, "pixel data does not match image size");
+
+    out.write_all(&SIGNATURE)?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &ihdr)?;
+
+    // every scanline is prefixed with filter type 0 (none)
+    let stride = width * channels;
+    let mut raw = Vec::with_capacity((stride + 1) * height);
+    for row in data.chunks(stride.max(1)).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Decodes a PNG file into 8-bit RGB. Alpha is dropped, 16-bit samples
+/// keep their high byte and low bit depths are scaled up to 8 bits.
+pub fn decode(data: &[u8]) -> io::Result<Decoded> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(invalid("not a PNG file"));
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data.get(pos + 8..pos + 8 + len).ok_or_else(|| invalid("truncated PNG chunk"))?;
+        match kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"PLTE" => palette = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {},
+        }
+        // skip the CRC as well
+        pos += 12 + len;
+    }
+
+    let header = header.ok_or_else(|| invalid("missing IHDR chunk"))?;
+    // the header's size is only trusted once the image data is known to
+    // cover it, so a bogus one can't overflow or reserve a huge buffer
+    let stride = header.stride().ok_or_else(|| invalid("PNG image too large"))?;
+    let raw_len = (stride + 1).checked_mul(header.height).ok_or_else(|| invalid("PNG image too large"))?;
+    let mut raw = inflate::zlib_decompress(&compressed)?;
+    if raw.len() < raw_len {
+        return Err(invalid("not enough image data"));
+    }
+    unfilter(&mut raw, stride, header.height, header.filter_distance())?;
+
+    let rgb_len = header.width.checked_mul(header.height).and_then(|pixels| pixels.checked_mul(3));
+    let mut rgb = Vec::with_capacity(rgb_len.ok_or_else(|| invalid("PNG image too large"))?);
+    for row in raw.chunks(stride + 1).take(header.height) {
+        let row = &row[1..];
+        for x in 0..header.width {
+            let pixel = match header.color_type {
+                COLOR_TYPE_GRAY | COLOR_TYPE_GRAY_ALPHA => {
+                    let g = header.sample(row, x, 0);
+                    [g, g, g]
+                },
+                COLOR_TYPE_PALETTE => {
+                    let index = header.raw_sample(row, x, 0) as usize * 3;
+                    let entry = palette.get(index..index + 3).ok_or_else(|| invalid("palette index out of range"))?;
+                    [entry[0], entry[1], entry[2]]
+                },
+                _ => [header.sample(row, x, 0), header.sample(row, x, 1), header.sample(row, x, 2)],
+            };
+            rgb.extend_from_slice(&pixel);
+        }
+    }
+
+    Ok(Decoded { width: header.width, height: header.height, rgb })
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn parse(body: &[u8]) -> io::Result<Header> {
+        if body.len() != 13 {
+            return Err(invalid("bad IHDR chunk"));
+        }
+        let width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+        let height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+        let (bit_depth, color_type, interlace) = (body[8], body[9], body[12]);
+
+        let valid_depth = match color_type {
+            COLOR_TYPE_GRAY => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            COLOR_TYPE_PALETTE => matches!(bit_depth, 1 | 2 | 4 | 8),
+            COLOR_TYPE_RGB | COLOR_TYPE_GRAY_ALPHA | COLOR_TYPE_RGBA => matches!(bit_depth, 8 | 16),
+            _ => return Err(invalid("unknown PNG color type")),
+        };
+        if !valid_depth {
+            return Err(invalid("invalid PNG bit depth"));
+        }
+        if interlace != 0 {
+            return Err(invalid("interlaced PNGs are not supported"));
+        }
+        if width == 0 || height == 0 {
+            return Err(invalid("empty PNG image"));
+        }
+        Ok(Header { width, height, bit_depth, color_type })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_TYPE_RGB => 3,
+            COLOR_TYPE_GRAY_ALPHA => 2,
+            COLOR_TYPE_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes per row without the filter byte; `None` if that overflows.
+    fn stride(&self) -> Option<usize> {
+        self.width.checked_mul(self.bits_per_pixel()).map(|bits| bits.div_ceil(8))
+    }
+
+    /// Byte distance to the "left" neighbour used by the filters.
+    fn filter_distance(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// The sample as stored: sub-byte values unpacked, 16-bit values
+    /// reduced to their high byte.
+    fn raw_sample(&self, row: &[u8], x: usize, channel: usize) -> u8 {
+        let index = x * self.channels() + channel;
+        match self.bit_depth {
+            16 => row[index * 2],
+            8 => row[index],
+            depth => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                (row[bit / 8] >> shift) & ((1 << depth) - 1)
+            },
+        }
+    }
+
+    /// The sample scaled to the full 8-bit range.
+    fn sample(&self, row: &[u8], x: usize, channel: usize) -> u8 {
+        let value = self.raw_sample(row, x, channel);
+        match self.bit_depth {
+            1 => value * 255,
+            2 => value * 85,
+            4 => value * 17,
+            _ => value,
+        }
+    }
+}
+
+/// Reverses the per-scanline filters in place. Each row of `raw` is a
+/// filter type byte followed by `stride` bytes.
+fn unfilter(raw: &mut [u8], stride: usize, height: usize, bpp: usize) -> io::Result<()> {
+    let mut previous = vec![0u8; stride];
+    for row in raw.chunks_mut(stride + 1).take(height) {
+        let (kind, line) = row.split_at_mut(1);
+        for i in 0..stride {
+            let left = if i >= bpp { line[i - bpp] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+            let predictor = match kind[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid("unknown PNG filter type")),
+            };
+            line[i] = line[i].wrapping_add(predictor);
+        }
+        previous.copy_from_slice(line);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32_update(crc32_update(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn zlib_stored(raw: &[u8]) -> Vec<u8> {
+    let blocks = raw.len() / MAX_STORED_BLOCK + 1;
+    let mut z = Vec::with_capacity(raw.len() + blocks * 5 + 6);
+    z.extend_from_slice(&[0x78, 0x01]);
+
+    let mut chunks = raw.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        z.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        z.push(last as u8);
+        z.extend_from_slice(&len.to_le_bytes());
+        z.extend_from_slice(&(!len).to_le_bytes());
+        z.extend_from_slice(chunk);
+    }
+
+    z.extend_from_slice(&adler32(raw).to_be_bytes());
+    z
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest run that cannot overflow b before reducing
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
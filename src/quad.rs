@@ -0,0 +1,94 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb, AABB_PADDING};
+use crate::world::{INF};
+use crate::random::random_f64;
+
+/// Parallelogram spanned by edges `u` and `v` from corner `q`. Hits
+/// report `(u, v)` in `[0, 1]^2` across the surface.
+pub struct Quad {
+    q: Point,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f64,
+    // n / (n . n) with n = u x v; projects a planar offset onto (u, v)
+    w: Vec3,
+    area: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3, m: Arc<dyn Material>) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.length_squared(),
+            area: n.length(),
+            mat: m,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = self.normal.dot(ray.direct());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.org())) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let position = ray.range(t);
+        let planar = position - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let front_face = denom < 0.0;
+        let normal = if front_face { self.normal } else { -self.normal };
+        Some(HitRecord::new(
+            t,
+            position,
+            normal,
+            front_face,
+            Arc::clone(&self.mat),
+        ).with_uv(alpha, beta).with_tangent(self.u.unit()))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonal1 = Aabb::new(self.q, self.q + self.u + self.v);
+        let diagonal2 = Aabb::new(self.q + self.u, self.q + self.v);
+        diagonal1.surrounding(&diagonal2).padded(AABB_PADDING)
+    }
+
+    /// Uniform over the quad's area, converted to solid angle.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        let Some(rec) = self.intersect(&Ray::new(*origin, *direction), t_min, INF) else {
+            return 0.0;
+        };
+        let distance_squared = rec.t() * rec.t() * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+        if cosine < 1e-12 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let p = self.q + random_f64() * self.u + random_f64() * self.v;
+        p - *origin
+    }
+}
@@ -0,0 +1,37 @@
+//! Per-thread random number source used by all sampling code. Workers can
+//! reseed it so that a render with a fixed seed is reproducible no matter
+//! how the work is scheduled across threads.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::ops::Range;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Restarts the current thread's generator from `seed`.
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Derives an independent seed for one stream (tile, frame, ...) of a seeded render.
+pub fn stream_seed(seed: u64, stream: u64) -> u64 {
+    splitmix64(seed ^ splitmix64(stream))
+}
+
+pub fn random_range(range: Range<f64>) -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen_range(range))
+}
+
+/// Uniform sample in `[0, 1)`.
+pub fn random_f64() -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -1,19 +1,39 @@
 use crate::vec3::{Point, Vec3};
 use crate::material::{Material};
+use crate::aabb::{Aabb};
+use std::sync::{Arc};
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     origin: Point,
-    direction: Vec3
+    direction: Vec3,
+    time: f64,
+    wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(org: Point, direct: Vec3) -> Ray {
+        Ray::with_time(org, direct, 0.0)
+    }
+
+    /// A ray cast at `time` within the camera's shutter interval; moving
+    /// objects are intersected where they are at that moment.
+    pub fn with_time(org: Point, direct: Vec3, time: f64) -> Ray {
         Ray {
-            origin: org, 
-            direction: direct
+            origin: org,
+            direction: direct,
+            time,
+            wavelength: None,
         }
     }
 
+    /// The same ray carrying a single wavelength in nanometers, as split
+    /// off by a dispersive material.
+    pub fn with_wavelength(mut self, nm: f64) -> Ray {
+        self.wavelength = Some(nm);
+        self
+    }
+
     pub fn org(&self) -> &Point {
         &self.origin
     }
@@ -22,41 +42,85 @@ impl Ray {
         &self.direction
     }
 
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// `None` for ordinary rays, which carry all of RGB.
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn range(&self, pos: f64) -> Point {
         self.origin + self.direction * pos
     }
 
     pub fn diffuse(normal: &Vec3) -> Vec3 {
-        let reflect = Vec3::random_unit_vec();
+        let reflect = Vec3::random_unit_vector();
         if reflect.dot(normal) > 0.0 {
             reflect
         } else {
-            reflect.reverse()
+            -reflect
         }
     }
 }
 
 
-#[derive(PartialEq)]
+#[derive(Clone)]
 pub struct HitRecord {
     t: f64,
     pos: Point,
     normal: Vec3,
     front_face: bool,
-    mat: Material,
+    mat: Arc<dyn Material>,
+    u: f64,
+    v: f64,
+    tangent: Option<Vec3>,
 }
 
 impl HitRecord {
-    pub fn new(t: f64, p: Point, n: Vec3, front: bool, m: Material) -> HitRecord {
+    pub fn new(t: f64, p: Point, n: Vec3, front: bool, m: Arc<dyn Material>) -> HitRecord {
         HitRecord {
-            t: t,
+            t,
             pos: p,
             normal: n,
             front_face: front,
             mat: m,
+            u: 0.0,
+            v: 0.0,
+            tangent: None,
         } 
     }
 
+    /// Attaches surface coordinates to the hit, e.g. texture UVs or the
+    /// barycentric weights of a triangle.
+    pub fn with_uv(mut self, u: f64, v: f64) -> HitRecord {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    /// Attaches the unit surface tangent pointing towards increasing `u`,
+    /// which orients tangent-space normal maps.
+    pub fn with_tangent(mut self, tangent: Vec3) -> HitRecord {
+        self.tangent = Some(tangent);
+        self
+    }
+
+    /// Replaces the hit position and normal, e.g. when a transform wrapper
+    /// maps a hit from object space back to world space.
+    pub fn with_geometry(mut self, pos: Point, normal: Vec3) -> HitRecord {
+        self.pos = pos;
+        self.normal = normal;
+        self
+    }
+
+    /// Replaces the material, e.g. when a wrapper re-skins an object.
+    pub fn with_material(mut self, mat: Arc<dyn Material>) -> HitRecord {
+        self.mat = mat;
+        self
+    }
+
     pub fn t(&self) -> f64 {
         self.t
     }
@@ -73,12 +137,43 @@ impl HitRecord {
         self.front_face
     }
 
-    pub fn mat(&self) -> &Material {
-        &self.mat
+    pub fn mat(&self) -> &dyn Material {
+        &*self.mat
+    }
+
+    pub fn u(&self) -> f64 {
+        self.u
+    }
+
+    pub fn v(&self) -> f64 {
+        self.v
+    }
+
+    /// Surface tangent along `u`, for shapes that provide one.
+    pub fn tangent(&self) -> Option<&Vec3> {
+        self.tangent.as_ref()
     }
 }
 
 pub trait Hittable: Sync + Send {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Box enclosing everything the object can be hit at. Unbounded
+    /// objects return `Aabb::UNIVERSE`, empty ones `Aabb::EMPTY`.
+    fn bounding_box(&self) -> Aabb;
+
+    /// Solid-angle density with which `random(origin)` returns `direction`.
+    /// Only hits past `t_min` along `direction` count, so the probe skips
+    /// the surface `origin` sits on the same way the shadow ray does.
+    /// Objects that cannot be sampled as lights keep the default of 0.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec3, _t_min: f64) -> f64 {
+        0.0
+    }
+
+    /// Random direction from `origin` towards the object, used to aim
+    /// shadow rays at lights.
+    fn random(&self, _origin: &Point) -> Vec3 {
+        Vec3::new([1.0, 0.0, 0.0])
+    }
 }
 
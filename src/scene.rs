@@ -0,0 +1,134 @@
+use crate::ray::{Hittable};
+use crate::background::{Background};
+use crate::camera::{Camera, CameraBuilder};
+use crate::color::{Color};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::quad::{Quad};
+use crate::sphere::{Sphere};
+use crate::texture::{CheckerTexture};
+use crate::vec3::{Point, Vec3};
+use crate::world::{World};
+use std::sync::{Arc};
+
+/// Everything a camera renders: the geometry, the lights to sample
+/// directly and the background. Settings left unset fall back to the
+/// camera's `background` and `lights`.
+#[derive(Clone)]
+pub struct Scene {
+    world: Arc<dyn Hittable>,
+    lights: Option<Vec<Arc<dyn Hittable>>>,
+    background: Option<Background>,
+}
+
+impl Scene {
+    /// A scene of just `world`, using the camera's background and lights.
+    pub fn new(world: Arc<dyn Hittable>) -> Scene {
+        Scene::builder(world).build()
+    }
+
+    pub fn builder(world: Arc<dyn Hittable>) -> SceneBuilder {
+        SceneBuilder::new(world)
+    }
+
+    pub fn world(&self) -> &Arc<dyn Hittable> {
+        &self.world
+    }
+
+    pub fn lights(&self) -> Option<&[Arc<dyn Hittable>]> {
+        self.lights.as_deref()
+    }
+
+    pub fn background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+}
+
+/// Collects the parts of a `Scene`; only the world is required.
+#[derive(Clone)]
+pub struct SceneBuilder {
+    world: Arc<dyn Hittable>,
+    lights: Option<Vec<Arc<dyn Hittable>>>,
+    background: Option<Background>,
+}
+
+impl SceneBuilder {
+    pub fn new(world: Arc<dyn Hittable>) -> SceneBuilder {
+        SceneBuilder {
+            world,
+            lights: None,
+            background: None,
+        }
+    }
+
+    /// Emitters to sample directly, see `CameraBuilder::lights`. Each one
+    /// must also be part of the world.
+    pub fn lights(mut self, lights: Vec<Arc<dyn Hittable>>) -> SceneBuilder {
+        self.lights = Some(lights);
+        self
+    }
+
+    /// Adds one emitter to the lights sampled directly.
+    pub fn light(mut self, light: Arc<dyn Hittable>) -> SceneBuilder {
+        self.lights.get_or_insert_with(Vec::new).push(light);
+        self
+    }
+
+    pub fn background(mut self, background: Background) -> SceneBuilder {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn build(&self) -> Scene {
+        Scene {
+            world: Arc::clone(&self.world),
+            lights: self.lights.clone(),
+            background: self.background.clone(),
+        }
+    }
+}
+
+/// A small fixed scene for benchmarks and regression tests: diffuse,
+/// glass and metal spheres on a checkered floor under an area light.
+/// The camera comes seeded and quiet at a low resolution, so every render
+/// of it is identical and quick.
+pub fn reference_scene() -> (CameraBuilder, Scene) {
+    let mut world = World::new();
+    let floor = Lambertian::textured(Arc::new(CheckerTexture::from_colors(
+        3.0,
+        Color::rgb(0.2, 0.3, 0.1),
+        Color::rgb(0.9, 0.9, 0.9),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point::new([-5.0, -1.0, -5.0]),
+        Vec3::new([10.0, 0.0, 0.0]),
+        Vec3::new([0.0, 0.0, 10.0]),
+        Arc::new(floor),
+    )));
+    world.add(Arc::new(Sphere::new(Point::new([-2.2, 0.0, 0.0]), 1.0, Arc::new(Lambertian::new(Color::rgb(0.7, 0.3, 0.2))))));
+    world.add(Arc::new(Sphere::new(Point::new([0.0, 0.0, 0.0]), 1.0, Arc::new(Dielectric::new(1.5)))));
+    world.add(Arc::new(Sphere::new(Point::new([2.2, 0.0, 0.0]), 1.0, Arc::new(Metal::new(Color::rgb(0.8, 0.8, 0.9), 0.1)))));
+    let light: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new([-1.0, 3.0, -1.0]),
+        Vec3::new([2.0, 0.0, 0.0]),
+        Vec3::new([0.0, 0.0, 2.0]),
+        Arc::new(DiffuseLight::new(Color::rgb(6.0, 6.0, 6.0))),
+    ));
+    world.add(Arc::clone(&light));
+
+    let camera = Camera::builder()
+        .look_from(Point::new([0.0, 1.5, 8.0]))
+        .look_at(Point::new([0.0, -0.2, 0.0]))
+        .v_fov(35.0)
+        .defocus_angle(0.0)
+        .width(96)
+        .aspect_ratio(1.5)
+        .samples(16)
+        .max_depth(8)
+        .seed(1)
+        .quiet(true);
+    let scene = Scene::builder(Arc::new(world.into_bvh()))
+        .light(light)
+        .background(Background::VerticalGradient(Color::rgb(0.1, 0.1, 0.1), Color::rgb(0.3, 0.4, 0.6)))
+        .build();
+    (camera, scene)
+}
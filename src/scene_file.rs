@@ -0,0 +1,560 @@
+//! Scene descriptions in JSON.
+//!
+//! ```json
+//! {
+//!   "camera": { "look_from": [13, 2, 3], "look_at": [0, 0, 0], "width": 800, "samples": 100 },
+//!   "background": { "type": "gradient", "bottom": [1, 1, 1], "top": [0.5, 0.7, 1] },
+//!   "materials": {
+//!     "ground": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] }
+//!   },
+//!   "objects": [
+//!     { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+//!     { "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": { "type": "dielectric", "ior": 1.5 } },
+//!     { "type": "quad", "q": [-1, 3, -1], "u": [2, 0, 0], "v": [0, 0, 2],
+//!       "material": { "type": "diffuse_light", "emit": [4, 4, 4] }, "light": true }
+//!   ]
+//! }
+//! ```
+//!
+//! Camera keys: `look_from`, `look_at`, `up`, `v_fov`, `width`, `height`,
+//! `aspect_ratio`, `samples`, `max_depth`, `threads`, `terminal_color`,
+//! `path_regularization`, `shadow_epsilon`, `world_scale` (scene units per
+//! meter), `focus_dist`, `focus_on` (a point, which wins over
+//! `focus_dist`), `defocus_angle`, `focal_length` and `f_number` (a real
+//! lens, overriding `v_fov` and `defocus_angle`; without `f_number` it is
+//! a pinhole), `sensor_height`, `gamma`, `exposure`, `tone_map` (`none`,
+//! `reinhard` or `aces`), `seed` (a string of digits above 2^53),
+//! `preview_stride`, `time_budget` (seconds, replacing `samples`), and
+//! the booleans `transparent_background` and `skip_empty_tiles`.
+//!
+//! Backgrounds: `solid` (`color`), `gradient` (`bottom`, `top`) and
+//! `environment` (`path`, optional `intensity`).
+//!
+//! Objects: `sphere` (`center`, `radius`), `quad` (`q`, `u`, `v`), `disk`
+//! (`center`, `normal`, `radius`), `box` (`min`, `max`) and `cylinder` (end
+//! points `a` and `b`, `radius`, optional `capped` defaulting to true),
+//! each with a `material`. All but cylinders can be marked `"light": true`
+//! to be sampled directly.
+//!
+//! Materials, inline or named in `materials`: `lambertian` (`albedo`),
+//! `metal` (`albedo`, optional `fuzz`, or `rough_u` and `rough_v` for
+//! brushed metal), `coated` (`albedo` under a clear coat of index `ior`,
+//! optional `roughness`), `dielectric` (`ior`, optional Cauchy dispersion
+//! coefficients `cauchy_b` and `cauchy_c`) and `diffuse_light` (`emit`, or
+//! a color temperature `kelvin` with an optional `intensity`).
+//!
+//! Colors are arrays of linear RGB values or sRGB hex codes like
+//! `"#ff8800"`.
+//!
+//! Unknown keys are errors, so typos don't go unnoticed.
+use crate::json::{self, Json};
+use crate::camera::{Camera, CameraBuilder};
+use crate::config::{RenderConfig};
+use crate::scene::{Scene};
+use crate::background::{Background, EnvMap};
+use crate::material::{Material, Lambertian, Metal, Coated, Dielectric, DiffuseLight};
+use crate::sphere::{Sphere};
+use crate::quad::{Quad};
+use crate::box_prim::{BoxPrim};
+use crate::cylinder::{Cylinder};
+use crate::disk::{Disk};
+use crate::world::{World};
+use crate::ray::{Hittable};
+use crate::texture::{TextureError};
+use crate::vec3::{Vec3};
+use crate::color::{Color, ToneMap};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc};
+use std::time::Duration;
+
+/// 2^53, beyond which JSON numbers (doubles) skip integers.
+pub(crate) const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    /// Malformed JSON.
+    Syntax { line: usize, message: String },
+    /// Well-formed JSON that doesn't describe a scene; `path` locates the
+    /// offending value, e.g. `objects[2].material`.
+    Invalid { path: String, message: String },
+    /// An environment map referenced by the scene could not be loaded.
+    Texture { path: String, error: TextureError },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene file: {}", e),
+            SceneError::Syntax { line, message } => write!(f, "scene file line {}: {}", line, message),
+            SceneError::Invalid { path, message } => write!(f, "{}: {}", path, message),
+            SceneError::Texture { path, error } => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(e) => Some(e),
+            SceneError::Texture { error, .. } => Some(error),
+            SceneError::Syntax { .. } | SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Loads a JSON scene file (see the module docs for the format) into a
+/// camera builder and the scene it looks at. The builder still accepts
+/// further settings, such as threads or a progress callback. Relative
+/// image paths are resolved against the file's directory.
+pub fn load_scene(path: impl AsRef<Path>) -> Result<(CameraBuilder, Scene), SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(SceneError::Io)?;
+    parse_scene_in(&source, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Parses JSON scene text; see `load_scene`. Relative image paths are
+/// resolved against the working directory.
+pub fn parse_scene(source: &str) -> Result<(CameraBuilder, Scene), SceneError> {
+    parse_scene_in(source, Path::new(""))
+}
+
+fn parse_scene_in(source: &str, base_dir: &Path) -> Result<(CameraBuilder, Scene), SceneError> {
+    let document = json::parse(source).map_err(|e| SceneError::Syntax { line: e.line, message: e.message })?;
+    let mut root = Fields::new(&document, "scene".to_string())?;
+
+    let camera = match root.take("camera") {
+        Some(value) => parse_camera(value)?,
+        None => CameraBuilder::new(),
+    };
+    let background = root.take("background").map(|value| parse_background(value, base_dir)).transpose()?;
+
+    let mut materials = HashMap::new();
+    if let Some(value) = root.take("materials") {
+        let mut named = Fields::new(value, "materials".to_string())?;
+        for (name, value) in named.take_all() {
+            materials.insert(name.clone(), parse_material(value, &format!("materials.{}", name), &materials)?);
+        }
+    }
+
+    let mut world = World::new();
+    let mut lights = Vec::new();
+    let objects = root.required("objects")?;
+    let Json::Array(objects) = objects else {
+        return Err(invalid("objects", format!("expected an array, got {}", objects.kind())));
+    };
+    for (index, value) in objects.iter().enumerate() {
+        let (object, is_light) = parse_object(value, &format!("objects[{}]", index), &materials)?;
+        if is_light {
+            lights.push(Arc::clone(&object));
+        }
+        world.add(object);
+    }
+    root.finish()?;
+
+    let mut scene = Scene::builder(Arc::new(world.into_bvh()));
+    if !lights.is_empty() {
+        scene = scene.lights(lights);
+    }
+    if let Some(background) = background {
+        scene = scene.background(background);
+    }
+    Ok((camera, scene.build()))
+}
+
+/// Parses a config file; see `RenderConfig::load`.
+pub(crate) fn parse_config_in(source: &str, base_dir: &Path) -> Result<RenderConfig, SceneError> {
+    let document = json::parse(source).map_err(|e| SceneError::Syntax { line: e.line, message: e.message })?;
+    let mut fields = Fields::new(&document, "config".to_string())?;
+    let mut config = parse_config(&mut fields)?;
+    if let Some(value) = fields.take("background") {
+        config.background = parse_background(value, base_dir)?;
+    }
+    fields.finish()?;
+    Ok(config)
+}
+
+/// The `RenderConfig` keys among `fields`, shared by config files and the
+/// camera of scene files. The background is left to the caller, as scene
+/// files keep it outside the camera.
+fn parse_config(fields: &mut Fields) -> Result<RenderConfig, SceneError> {
+    let mut config = RenderConfig::default();
+    if let Some(width) = fields.integer("width")? {
+        config.width = width;
+    }
+    config.height = fields.integer("height")?;
+    if let Some(samples) = fields.integer("samples")? {
+        config.samples = samples;
+    }
+    if let Some(max_depth) = fields.integer("max_depth")? {
+        config.max_depth = max_depth;
+    }
+    config.threads = fields.integer("threads")?;
+    if let Some(name) = fields.string("tone_map")? {
+        config.tone_map = ToneMap::from_name(name).ok_or_else(|| {
+            fields.invalid_field("tone_map", format!("unknown tone map \"{}\", expected none, reinhard or aces", name))
+        })?;
+    }
+    if let Some(exposure) = fields.number("exposure")? {
+        config.exposure = exposure;
+    }
+    config.seed = fields.seed("seed")?;
+    Ok(config)
+}
+
+fn parse_camera(value: &Json) -> Result<CameraBuilder, SceneError> {
+    let mut fields = Fields::new(value, "camera".to_string())?;
+    let mut camera = Camera::from_config(parse_config(&mut fields)?);
+    if let Some(look_from) = fields.vec3("look_from")? {
+        camera = camera.look_from(look_from);
+    }
+    if let Some(look_at) = fields.vec3("look_at")? {
+        camera = camera.look_at(look_at);
+    }
+    if let Some(up) = fields.vec3("up")? {
+        camera = camera.up(up);
+    }
+    if let Some(v_fov) = fields.number("v_fov")? {
+        camera = camera.v_fov(v_fov);
+    }
+    if let Some(aspect_ratio) = fields.number("aspect_ratio")? {
+        camera = camera.aspect_ratio(aspect_ratio);
+    }
+    if let Some(color) = fields.color("terminal_color")? {
+        camera = camera.terminal_color(color);
+    }
+    if let Some(roughness) = fields.number("path_regularization")? {
+        camera = camera.path_regularization(roughness);
+    }
+    if let Some(epsilon) = fields.number("shadow_epsilon")? {
+        camera = camera.shadow_epsilon(epsilon);
+    }
+    if let Some(units_per_meter) = fields.number("world_scale")? {
+        camera = camera.world_scale(units_per_meter);
+    }
+    if let Some(focus_dist) = fields.number("focus_dist")? {
+        camera = camera.focus_dist(focus_dist);
+    }
+    if let Some(point) = fields.vec3("focus_on")? {
+        camera = camera.focus_on(point);
+    }
+    if let Some(defocus_angle) = fields.number("defocus_angle")? {
+        camera = camera.defocus_angle(defocus_angle);
+    }
+    let f_number = fields.number("f_number")?;
+    if let Some(focal_length) = fields.number("focal_length")? {
+        camera = camera.lens(focal_length, f_number.unwrap_or(f64::INFINITY));
+    } else if f_number.is_some() {
+        return Err(fields.invalid_field("f_number", "needs a focal_length".to_string()));
+    }
+    if let Some(sensor_height) = fields.number("sensor_height")? {
+        camera = camera.sensor_height(sensor_height);
+    }
+    if let Some(gamma) = fields.number("gamma")? {
+        camera = camera.gamma(gamma);
+    }
+    if let Some(transparent) = fields.boolean("transparent_background")? {
+        camera = camera.transparent_background(transparent);
+    }
+    if let Some(skip) = fields.boolean("skip_empty_tiles")? {
+        camera = camera.skip_empty_tiles(skip);
+    }
+    if let Some(stride) = fields.integer("preview_stride")? {
+        camera = camera.preview_stride(stride);
+    }
+    if let Some(seconds) = fields.number("time_budget")? {
+        let budget = Duration::try_from_secs_f64(seconds)
+            .map_err(|_| fields.invalid_field("time_budget", format!("expected a non-negative number of seconds, got {}", seconds)))?;
+        camera = camera.time_budget(budget);
+    }
+    fields.finish()?;
+    Ok(camera)
+}
+
+fn parse_background(value: &Json, base_dir: &Path) -> Result<Background, SceneError> {
+    let mut fields = Fields::new(value, "background".to_string())?;
+    let background = match fields.kind()?.as_str() {
+        "solid" => Background::SolidColor(fields.required_color("color")?),
+        "gradient" => Background::VerticalGradient(fields.required_color("bottom")?, fields.required_color("top")?),
+        "environment" => {
+            let path = base_dir.join(fields.required_string("path")?);
+            let map = EnvMap::load(&path).map_err(|error| SceneError::Texture {
+                path: "background.path".to_string(),
+                error,
+            })?;
+            let intensity = fields.number("intensity")?.unwrap_or(1.0);
+            Background::Environment(map.with_intensity(intensity))
+        },
+        other => return Err(fields.invalid(format!("unknown background type \"{}\"", other))),
+    };
+    fields.finish()?;
+    Ok(background)
+}
+
+fn parse_object(
+    value: &Json,
+    path: &str,
+    materials: &HashMap<String, Arc<dyn Material>>,
+) -> Result<(Arc<dyn Hittable>, bool), SceneError> {
+    let mut fields = Fields::new(value, path.to_string())?;
+    let kind = fields.kind()?;
+    let material = parse_material(fields.required("material")?, &format!("{}.material", path), materials)?;
+    let object: Arc<dyn Hittable> = match kind.as_str() {
+        "sphere" => Arc::new(Sphere::new(fields.required_vec3("center")?, fields.required_number("radius")?, material)),
+        "quad" => Arc::new(Quad::new(
+            fields.required_vec3("q")?,
+            fields.required_vec3("u")?,
+            fields.required_vec3("v")?,
+            material,
+        )),
+        "box" => Arc::new(BoxPrim::new(fields.required_vec3("min")?, fields.required_vec3("max")?, material)),
+        "disk" => {
+            let (center, normal) = (fields.required_vec3("center")?, fields.required_vec3("normal")?);
+            let radius = fields.required_number("radius")?;
+            if radius <= 0.0 {
+                return Err(fields.invalid_field("radius", format!("expected a positive radius, got {}", radius)));
+            }
+            Arc::new(Disk::new(center, normal, radius, material))
+        },
+        "cylinder" => {
+            let (a, b) = (fields.required_vec3("a")?, fields.required_vec3("b")?);
+            let radius = fields.required_number("radius")?;
+            if radius <= 0.0 {
+                return Err(fields.invalid_field("radius", format!("expected a positive radius, got {}", radius)));
+            }
+            if a == b {
+                return Err(fields.invalid_field("b", "must differ from `a`".to_string()));
+            }
+            if fields.boolean("capped")?.unwrap_or(true) {
+                Arc::new(Cylinder::new(a, b, radius, material))
+            } else {
+                Arc::new(Cylinder::open(a, b, radius, material))
+            }
+        },
+        other => return Err(fields.invalid(format!("unknown object type \"{}\"", other))),
+    };
+    // cylinders can't be sampled as lights, so "light" stays an unknown key for them
+    let is_light = match kind.as_str() {
+        "sphere" | "quad" | "disk" | "box" => fields.boolean("light")?.unwrap_or(false),
+        _ => false,
+    };
+    fields.finish()?;
+    Ok((object, is_light))
+}
+
+/// A material given inline, or by name from the `materials` table.
+fn parse_material(
+    value: &Json,
+    path: &str,
+    materials: &HashMap<String, Arc<dyn Material>>,
+) -> Result<Arc<dyn Material>, SceneError> {
+    if let Json::String(name) = value {
+        return materials.get(name).cloned()
+            .ok_or_else(|| invalid(path, format!("no material named \"{}\"", name)));
+    }
+
+    let mut fields = Fields::new(value, path.to_string())?;
+    let material: Arc<dyn Material> = match fields.kind()?.as_str() {
+        "lambertian" => Arc::new(Lambertian::new(fields.required_color("albedo")?)),
+        "metal" => {
+            let albedo = fields.required_color("albedo")?;
+            let fuzz = fields.number("fuzz")?;
+            match (fields.number("rough_u")?, fields.number("rough_v")?) {
+                (None, None) => Arc::new(Metal::new(albedo, fuzz.unwrap_or(0.0))),
+                (rough_u, rough_v) => {
+                    if fuzz.is_some() {
+                        return Err(fields.invalid_field("fuzz", "can't be combined with `rough_u` and `rough_v`".to_string()));
+                    }
+                    Arc::new(Metal::anisotropic(albedo, rough_u.unwrap_or(0.0), rough_v.unwrap_or(0.0)))
+                }
+            }
+        },
+        "coated" => Arc::new(Coated::new(
+            fields.required_color("albedo")?,
+            fields.required_ior("ior")?,
+            fields.number("roughness")?.unwrap_or(0.0),
+        )),
+        "dielectric" => {
+            let dielectric = Dielectric::new(fields.required_ior("ior")?);
+            let b = fields.number("cauchy_b")?;
+            let c = fields.number("cauchy_c")?;
+            match (b, c) {
+                (None, None) => Arc::new(dielectric),
+                (b, c) => Arc::new(dielectric.with_dispersion(b.unwrap_or(0.0), c.unwrap_or(0.0))),
+            }
+        },
+        "diffuse_light" => match fields.number("kelvin")? {
+            Some(kelvin) => Arc::new(DiffuseLight::from_kelvin(kelvin, fields.number("intensity")?.unwrap_or(1.0))),
+            None => Arc::new(DiffuseLight::new(fields.required_color("emit")?)),
+        },
+        other => return Err(fields.invalid(format!("unknown material type \"{}\"", other))),
+    };
+    fields.finish()?;
+    Ok(material)
+}
+
+fn invalid(path: &str, message: String) -> SceneError {
+    SceneError::Invalid { path: path.to_string(), message }
+}
+
+/// Reads the members of one JSON object, remembering which were used so
+/// leftovers can be reported as unknown.
+struct Fields<'a> {
+    path: String,
+    members: &'a [(String, Json)],
+    used: Vec<bool>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(value: &'a Json, path: String) -> Result<Fields<'a>, SceneError> {
+        match value {
+            Json::Object(members) => Ok(Fields { path, members, used: vec![false; members.len()] }),
+            other => Err(invalid(&path, format!("expected an object, got {}", other.kind()))),
+        }
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a Json> {
+        let index = self.members.iter().position(|(name, _)| name == key)?;
+        self.used[index] = true;
+        Some(&self.members[index].1)
+    }
+
+    fn take_all(&mut self) -> impl Iterator<Item = &'a (String, Json)> {
+        self.used.iter_mut().for_each(|used| *used = true);
+        self.members.iter()
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a Json, SceneError> {
+        self.take(key).ok_or_else(|| self.invalid(format!("missing \"{}\"", key)))
+    }
+
+    fn number(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Json::Number(n)) => Ok(Some(*n)),
+            Some(other) => Err(self.invalid_field(key, format!("expected a number, got {}", other.kind()))),
+        }
+    }
+
+    fn required_number(&mut self, key: &str) -> Result<f64, SceneError> {
+        self.number(key)?.ok_or_else(|| self.invalid(format!("missing \"{}\"", key)))
+    }
+
+    /// An index of refraction, which must be positive and finite.
+    fn required_ior(&mut self, key: &str) -> Result<f64, SceneError> {
+        let ior = self.required_number(key)?;
+        if !(ior > 0.0 && ior.is_finite()) {
+            return Err(self.invalid_field(key, format!("expected a positive index of refraction, got {}", ior)));
+        }
+        Ok(ior)
+    }
+
+    /// A non-negative whole number that fits in `T`.
+    fn integer<T: TryFrom<u64>>(&mut self, key: &str) -> Result<Option<T>, SceneError> {
+        let Some(n) = self.number(key)? else {
+            return Ok(None);
+        };
+        if n < 0.0 || n.fract() != 0.0 || n > u64::MAX as f64 {
+            return Err(self.invalid_field(key, format!("expected a whole number, got {}", n)));
+        }
+        T::try_from(n as u64).map(Some).map_err(|_| self.invalid_field(key, format!("{} is out of range", n)))
+    }
+
+    /// A whole number up to 2^53, the largest a JSON number holds exactly,
+    /// or any `u64` written as a string of digits.
+    fn seed(&mut self, key: &str) -> Result<Option<u64>, SceneError> {
+        if let Some(Json::String(digits)) = self.take(key) {
+            return digits.parse().map(Some)
+                .map_err(|_| self.invalid_field(key, format!("\"{}\" is not a whole number", digits)));
+        }
+        match self.integer::<u64>(key)? {
+            Some(seed) if seed > MAX_EXACT_INTEGER => {
+                Err(self.invalid_field(key, format!("{} is not exact as a number, write it as a string", seed)))
+            },
+            seed => Ok(seed),
+        }
+    }
+
+    fn boolean(&mut self, key: &str) -> Result<Option<bool>, SceneError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Json::Bool(b)) => Ok(Some(*b)),
+            Some(other) => Err(self.invalid_field(key, format!("expected a boolean, got {}", other.kind()))),
+        }
+    }
+
+    fn string(&mut self, key: &str) -> Result<Option<&'a str>, SceneError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Json::String(s)) => Ok(Some(s)),
+            Some(other) => Err(self.invalid_field(key, format!("expected a string, got {}", other.kind()))),
+        }
+    }
+
+    fn required_string(&mut self, key: &str) -> Result<&'a str, SceneError> {
+        self.string(key)?.ok_or_else(|| self.invalid(format!("missing \"{}\"", key)))
+    }
+
+    /// The `type` member that selects what the object describes.
+    fn kind(&mut self) -> Result<String, SceneError> {
+        self.required_string("type").map(str::to_string)
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        let Some(value) = self.take(key) else {
+            return Ok(None);
+        };
+        match value {
+            Json::Array(items) if items.len() == 3 => {
+                let mut e = [0.0; 3];
+                for (component, item) in e.iter_mut().zip(items) {
+                    let Json::Number(n) = item else {
+                        return Err(self.invalid_field(key, format!("expected numbers, got {}", item.kind())));
+                    };
+                    *component = *n;
+                }
+                Ok(Some(Vec3::new(e)))
+            },
+            _ => Err(self.invalid_field(key, "expected an array of 3 numbers".to_string())),
+        }
+    }
+
+    fn required_vec3(&mut self, key: &str) -> Result<Vec3, SceneError> {
+        self.vec3(key)?.ok_or_else(|| self.invalid(format!("missing \"{}\"", key)))
+    }
+
+    /// A linear RGB array, or an sRGB hex code such as `"#ff8800"`.
+    fn color(&mut self, key: &str) -> Result<Option<Color>, SceneError> {
+        if let Some(Json::String(hex)) = self.take(key) {
+            return match Color::from_hex(hex) {
+                Some(color) => Ok(Some(color)),
+                None => Err(self.invalid_field(key, format!("\"{}\" is not a hex color", hex))),
+            };
+        }
+        self.vec3(key)
+    }
+
+    fn required_color(&mut self, key: &str) -> Result<Color, SceneError> {
+        self.color(key)?.ok_or_else(|| self.invalid(format!("missing \"{}\"", key)))
+    }
+
+    /// Fails on the first member nothing asked for.
+    fn finish(self) -> Result<(), SceneError> {
+        match self.members.iter().zip(&self.used).find(|(_, used)| !**used) {
+            Some(((key, _), _)) => Err(self.invalid(format!("unknown field \"{}\"", key))),
+            None => Ok(()),
+        }
+    }
+
+    fn invalid(&self, message: String) -> SceneError {
+        invalid(&self.path, message)
+    }
+
+    fn invalid_field(&self, key: &str, message: String) -> SceneError {
+        invalid(&format!("{}.{}", self.path, key), message)
+    }
+}
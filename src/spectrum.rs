@@ -0,0 +1,93 @@
+//! Conversion of spectra to RGB: single wavelengths, for paths that a
+//! dispersive material has split into one wavelength, and blackbody
+//! radiation, for lights given by their color temperature.
+use crate::color::{self, Color, BLACK};
+use crate::random::random_range;
+use std::sync::OnceLock;
+
+/// Visible range wavelengths are drawn from, in nanometers.
+const MIN_WAVELENGTH: f64 = 380.0;
+const MAX_WAVELENGTH: f64 = 780.0;
+
+/// Picks a wavelength uniformly from the visible range and returns it with
+/// the RGB weight of a path at that wavelength. The weights average to
+/// white over the range, so a white path split this way stays white.
+pub(crate) fn sample_wavelength() -> (f64, Color) {
+    let nm = random_range(MIN_WAVELENGTH..MAX_WAVELENGTH);
+    let mean = mean_response();
+    let rgb = response(nm);
+    (nm, Color::new([0, 1, 2].map(|i| rgb[i] / mean[i])))
+}
+
+/// Linear sRGB of a unit of light at `nm`, with the out-of-gamut negative
+/// components dropped.
+fn response(nm: f64) -> Color {
+    let (x, y, z) = cie_xyz(nm);
+    color::clamp(xyz_to_rgb(x, y, z), 0.0, f64::INFINITY)
+}
+
+/// Color of a blackbody at `kelvin`, scaled to a luminance of 1. Planck's
+/// law is integrated against the color matching functions over the
+/// visible range; colors outside the sRGB gamut (deep red below about
+/// 1000 K) lose their negative components.
+pub(crate) fn blackbody(kelvin: f64) -> Color {
+    const STEPS: usize = 400;
+    let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / STEPS as f64;
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for i in 0..STEPS {
+        let nm = MIN_WAVELENGTH + (i as f64 + 0.5) * step;
+        let radiance = planck(nm, kelvin);
+        let (cx, cy, cz) = cie_xyz(nm);
+        x += radiance * cx;
+        y += radiance * cy;
+        z += radiance * cz;
+    }
+    let rgb = color::clamp(xyz_to_rgb(x, y, z), 0.0, f64::INFINITY);
+    let luminance = color::luminance(&rgb);
+    if luminance > 0.0 && luminance.is_finite() { rgb / luminance } else { BLACK }
+}
+
+/// Spectral radiance of a blackbody, up to a constant factor.
+fn planck(nm: f64, kelvin: f64) -> f64 {
+    // second radiation constant h c / k in nm K
+    const C2: f64 = 1.438_777e7;
+    let micrometers = nm / 1000.0;
+    1.0 / (micrometers.powi(5) * ((C2 / (nm * kelvin)).exp_m1()))
+}
+
+/// CIE XYZ to linear sRGB (D65 white).
+fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new([
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ])
+}
+
+/// Average of `response` over the sampled range, per channel.
+fn mean_response() -> Color {
+    static MEAN: OnceLock<Color> = OnceLock::new();
+    *MEAN.get_or_init(|| {
+        const STEPS: usize = 4000;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / STEPS as f64;
+        let mut sum = BLACK;
+        for i in 0..STEPS {
+            sum += response(MIN_WAVELENGTH + (i as f64 + 0.5) * step);
+        }
+        sum / STEPS as f64
+    })
+}
+
+/// CIE 1931 color matching functions, by the multi-lobe fit of Wyman,
+/// Sloan and Shirley (2013).
+fn cie_xyz(nm: f64) -> (f64, f64, f64) {
+    // Gaussian with different widths left and right of its peak
+    let g = |mu: f64, left: f64, right: f64| {
+        let t = (nm - mu) / if nm < mu { left } else { right };
+        (-0.5 * t * t).exp()
+    };
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
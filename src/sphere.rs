@@ -1,15 +1,21 @@
 use crate::ray::{Ray, HitRecord, Hittable};
 use crate::material::{Material};
-use crate::vec3::{Point};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::onb::{Onb};
+use std::f64::consts::PI;
+use crate::aabb::{Aabb};
+use crate::world::{INF};
+use crate::random::random_f64;
 
 pub struct Sphere {
     center: Point,
     radius: f64,
-    mat: Material,
+    mat: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub const fn new(p: Point, r: f64, m: Material) -> Sphere {
+    pub fn new(p: Point, r: f64, m: Arc<dyn Material>) -> Sphere {
         Sphere {
             center: p,
             radius: r,
@@ -20,47 +26,154 @@ impl Sphere {
 
 impl Hittable for Sphere {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = self.center - *ray.org();
-        let a = ray.direct().square();
-        let h = ray.direct().dot(&oc);
-        let c = oc.square() - self.radius * self.radius;
-        let delta = h * h - a * c;
+        intersect_sphere(self.center, self.radius, &self.mat, ray, t_min, t_max)
+    }
 
-        if delta < 0.0 {
-            return None;
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius.abs();
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    /// Uniform over the cone of directions that see the sphere, or over
+    /// all directions from inside it.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        if self.intersect(&Ray::new(*origin, *direction), t_min, INF).is_none() {
+            return 0.0;
+        }
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
         }
+    }
 
-        let delta_sqrt = delta.sqrt();
-        let x1 = (h - delta_sqrt) / a;
-        let x2 = (h + delta_sqrt) / a;
+    fn random(&self, origin: &Point) -> Vec3 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return Vec3::random_unit_vector();
+        };
+        // uniform in the cone around the direction to the center
+        let z = 1.0 + random_f64() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let basis = Onb::new(&(self.center - *origin));
+        basis.transform(&Vec3::new([r * phi.cos(), r * phi.sin(), z]))
+    }
+}
 
-        let root: f64;
-        if t_min <= x1 && x1 <= t_max {
-            root = x1;
-        } else if t_min <= x2 && x2 <= t_max {
-            root = x2;
-        } else {
+impl Sphere {
+    /// Cosine of the half-angle of the cone the sphere fills as seen from
+    /// `origin`, or `None` from inside.
+    fn cos_theta_max(&self, origin: &Point) -> Option<f64> {
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
             return None;
         }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+}
+
+/// Sphere whose center moves linearly from `center0` at time 0 to
+/// `center1` at time 1, blurring along its path while the shutter is open.
+pub struct MovingSphere {
+    center0: Point,
+    center1: Point,
+    radius: f64,
+    mat: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Point, center1: Point, radius: f64, m: Arc<dyn Material>) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            radius,
+            mat: m,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point {
+        self.center0 + time * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        intersect_sphere(self.center(ray.time()), self.radius, &self.mat, ray, t_min, t_max)
+    }
 
-        let position = ray.range(root);
-        let mut normal = (position - self.center) / self.radius;
-        let front_face = ray.direct().dot(&normal) < 0.0;
-        if !front_face { normal = normal.reverse(); }
-
-        Some(HitRecord::new(
-            root,
-            position,
-            normal,
-            front_face,
-            self.mat,
-        ))
+    /// Encloses the whole swept volume between the two end positions.
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius.abs();
+        let start = Aabb::new(self.center0 - r, self.center0 + r);
+        let end = Aabb::new(self.center1 - r, self.center1 + r);
+        start.surrounding(&end)
     }
 }
 
+fn intersect_sphere(center: Point, radius: f64, mat: &Arc<dyn Material>, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    let oc = center - *ray.org();
+    let a = ray.direct().length_squared();
+    let h = ray.direct().dot(&oc);
+    let c = oc.length_squared() - radius * radius;
+    let delta = h * h - a * c;
+
+    if delta < 0.0 {
+        return None;
+    }
+
+    let delta_sqrt = delta.sqrt();
+    let x1 = (h - delta_sqrt) / a;
+    let x2 = (h + delta_sqrt) / a;
+
+    let root: f64;
+    if t_min <= x1 && x1 <= t_max {
+        root = x1;
+    } else if t_min <= x2 && x2 <= t_max {
+        root = x2;
+    } else {
+        return None;
+    }
+
+    let position = ray.range(root);
+    let mut normal = (position - center) / radius;
+    let (u, v) = sphere_uv(&normal);
+    // direction of increasing longitude; undefined at the poles
+    let tangent = Vec3::new([normal.z(), 0.0, -normal.x()]);
+    let front_face = ray.direct().dot(&normal) < 0.0;
+    if !front_face { normal = -normal; }
+
+    let rec = HitRecord::new(
+        root,
+        position,
+        normal,
+        front_face,
+        Arc::clone(mat),
+    ).with_uv(u, v);
+    if tangent.near_zero() {
+        return Some(rec);
+    }
+    Some(rec.with_tangent(tangent.unit()))
+}
+
+/// Equirectangular coordinates of a point on the unit sphere: `u` goes
+/// around the `y` axis starting at `-x`, `v` from the south to the north pole.
+fn sphere_uv(p: &Vec3) -> (f64, f64) {
+    // rounding can push |y| just past 1 at the poles, where asin is NaN
+    let latitude = p.y().clamp(-1.0, 1.0).asin();
+    // atan2 covers (-pi, pi]; the seam falls on -x and both sides stay in [0, 1]
+    let longitude = (-p.z()).atan2(p.x()) + PI;
+    let u = (longitude / (2.0 * PI)).clamp(0.0, 1.0);
+    let v = 0.5 + latitude / PI;
+    (u, v)
+}
+
 impl Hittable for &Sphere {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         (*self).intersect(ray, t_min, t_max)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        (*self).bounding_box()
+    }
 }
 
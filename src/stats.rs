@@ -0,0 +1,136 @@
+//! Counters gathered while rendering. The hot paths bump cheap per-thread
+//! counts, which workers add to a shared `StatsCollector` after every band
+//! of rows, so the totals never cost a contended atomic per ray.
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+thread_local! {
+    static PATHS: Cell<u64> = const { Cell::new(0) };
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+    static BOUNCES: Cell<u64> = const { Cell::new(0) };
+    static BVH_NODES: Cell<u64> = const { Cell::new(0) };
+}
+
+fn bump(counter: &'static std::thread::LocalKey<Cell<u64>>) {
+    counter.with(|count| count.set(count.get() + 1));
+}
+
+/// A camera sample started a path.
+pub(crate) fn count_path() {
+    bump(&PATHS);
+}
+
+/// A ray was traced through the scene: path segments and shadow rays.
+pub(crate) fn count_ray() {
+    bump(&RAYS);
+}
+
+/// A path scattered off a surface.
+pub(crate) fn count_bounce() {
+    bump(&BOUNCES);
+}
+
+/// A ray was tested against a BVH node.
+pub(crate) fn count_bvh_node() {
+    bump(&BVH_NODES);
+}
+
+/// Takes the current thread's counts, leaving them at zero.
+fn take() -> [u64; 4] {
+    [&PATHS, &RAYS, &BOUNCES, &BVH_NODES].map(|counter| counter.with(|count| count.replace(0)))
+}
+
+/// Totals of a render, shared between its workers.
+#[derive(Default)]
+pub(crate) struct StatsCollector {
+    counts: [AtomicU64; 4],
+}
+
+impl StatsCollector {
+    /// Drops whatever the current thread counted outside of a render,
+    /// e.g. through `ray_color` calls, before it starts working for one.
+    pub(crate) fn start_thread(&self) {
+        take();
+    }
+
+    /// Adds the current thread's counts to the totals.
+    pub(crate) fn flush(&self) {
+        for (total, count) in self.counts.iter().zip(take()) {
+            total.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn finish(&self, elapsed: Duration) -> RenderStats {
+        let [paths, rays, bounces, bvh_nodes] = self.counts.each_ref().map(|total| total.load(Ordering::Relaxed));
+        RenderStats { paths, rays, bounces, bvh_nodes, elapsed }
+    }
+}
+
+/// What a render did, to understand why a scene is slow: how many rays it
+/// traced, how deep its paths went and how hard the BVH worked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderStats {
+    paths: u64,
+    rays: u64,
+    bounces: u64,
+    bvh_nodes: u64,
+    elapsed: Duration,
+}
+
+impl RenderStats {
+    /// Camera samples taken, one path each.
+    pub fn paths(&self) -> u64 {
+        self.paths
+    }
+
+    /// Rays traced through the scene: every path segment and shadow ray.
+    pub fn rays(&self) -> u64 {
+        self.rays
+    }
+
+    /// Times a path scattered off a surface.
+    pub fn bounces(&self) -> u64 {
+        self.bounces
+    }
+
+    /// BVH nodes, inner and leaf, that rays were tested against.
+    pub fn bvh_nodes(&self) -> u64 {
+        self.bvh_nodes
+    }
+
+    /// Wall-clock time of the render.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Bounces per path.
+    pub fn average_depth(&self) -> f64 {
+        self.bounces as f64 / self.paths.max(1) as f64
+    }
+
+    /// BVH nodes visited per ray; how well the hierarchy culls the scene.
+    pub fn bvh_nodes_per_ray(&self) -> f64 {
+        self.bvh_nodes as f64 / self.rays.max(1) as f64
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { self.rays as f64 / seconds } else { 0.0 }
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} rays in {:.2?} ({:.0} rays/s), {:.2} bounces per path, {:.1} BVH nodes per ray",
+            self.rays,
+            self.elapsed,
+            self.rays_per_second(),
+            self.average_depth(),
+            self.bvh_nodes_per_ray(),
+        )
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::color::{Color, srgb_to_linear};
+use crate::hdr;
+use crate::image::{Image};
+use crate::perlin::{Perlin};
+#[cfg(feature = "png")]
+use crate::png;
+use crate::vec3::{Point};
+
+
+/// Spatially varying color, looked up by surface coordinates `(u, v)`
+/// and the hit position `p`.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color;
+}
+
+/// The same color everywhere; lets plain colors stand in for textures.
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        self.color
+    }
+}
+
+impl From<Color> for SolidColor {
+    fn from(color: Color) -> SolidColor {
+        SolidColor::new(color)
+    }
+}
+
+/// 3D checkerboard alternating between two textures. A larger `scale`
+/// gives smaller checks.
+pub struct CheckerTexture {
+    scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> CheckerTexture {
+        CheckerTexture { scale, even, odd }
+    }
+
+    /// Checkerboard of two solid colors.
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> CheckerTexture {
+        CheckerTexture::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        let sines = (self.scale * p.x()).sin()
+            * (self.scale * p.y()).sin()
+            * (self.scale * p.z()).sin();
+        if sines < 0.0 {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+}
+
+/// Gray procedural texture built on Perlin noise. `scale` sets the
+/// frequency (larger gives finer detail) and `depth` the number of
+/// turbulence octaves; `depth` of 0 gives plain smooth noise.
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    depth: u32,
+    marble: bool,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, depth: u32) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+            depth,
+            marble: false,
+        }
+    }
+
+    /// Marble-like veins: a sine along `z` whose phase is perturbed by
+    /// the turbulence.
+    pub fn marble(scale: f64, depth: u32) -> NoiseTexture {
+        NoiseTexture {
+            marble: true,
+            ..NoiseTexture::new(scale, depth)
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point) -> Color {
+        let p = self.scale * *p;
+        let gray = if self.marble {
+            0.5 * (1.0 + (p.z() + 10.0 * self.noise.turbulence(&p, self.depth.max(1))).sin())
+        } else if self.depth == 0 {
+            0.5 * (1.0 + self.noise.noise(&p))
+        } else {
+            self.noise.turbulence(&p, self.depth)
+        };
+        Color::new([gray, gray, gray])
+    }
+}
+
+/// Why an image texture could not be loaded.
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "could not read texture: {}", e),
+            TextureError::Format(message) => write!(f, "invalid texture image: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io(e) => Some(e),
+            TextureError::Format(_) => None,
+        }
+    }
+}
+
+/// Image mapped onto a surface through its `(u, v)` coordinates, with
+/// `v = 0` at the bottom row. Lookups are bilinearly filtered and clamp
+/// at the edges, so a 2:1 equirectangular map wraps a sphere cleanly.
+/// Texels are held linear, so filtering blends light rather than encoded
+/// values, which would darken the edges between bright and dark texels.
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    /// Texture of an sRGB encoded image, such as the colors of an 8-bit
+    /// photo or painting, decoded to linear up front.
+    pub fn new_srgb(image: Image) -> ImageTexture {
+        let pixels = image.pixels().iter().map(|c| Color::new([0, 1, 2].map(|i| srgb_to_linear(c[i])))).collect();
+        ImageTexture::new_linear(Image::new(image.width(), image.height(), pixels))
+    }
+
+    /// Texture of an image whose values are used as they are: renders,
+    /// HDR maps, and data such as normal maps that must not be decoded.
+    pub fn new_linear(image: Image) -> ImageTexture {
+        assert!(image.width() > 0 && image.height() > 0, "texture image is empty");
+        ImageTexture { image }
+    }
+
+    /// Loads a PNG (with the `png` feature), a Radiance HDR or a
+    /// binary/ASCII PPM file, recognized by its contents rather than its
+    /// extension. PNG and PPM colors are taken to be sRGB and decoded;
+    /// HDR values are kept linear and may exceed 1.0.
+    pub fn load(path: impl AsRef<Path>) -> Result<ImageTexture, TextureError> {
+        let data = fs::read(path).map_err(TextureError::Io)?;
+        let image = decode_image(&data)?;
+        Ok(if data.starts_with(b"#?") { ImageTexture::new_linear(image) } else { ImageTexture::new_srgb(image) })
+    }
+
+    /// Like `load`, but keeps the stored values of every format as they
+    /// are, e.g. for normal maps.
+    pub fn load_linear(path: impl AsRef<Path>) -> Result<ImageTexture, TextureError> {
+        let data = fs::read(path).map_err(TextureError::Io)?;
+        decode_image(&data).map(ImageTexture::new_linear)
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Like `value`, but `u` wraps around instead of clamping, so the left
+    /// and right edges blend into each other as in a panorama.
+    pub(crate) fn value_wrapped(&self, u: f64, v: f64) -> Color {
+        self.bilinear(u.rem_euclid(1.0), v, true)
+    }
+
+    fn bilinear(&self, u: f64, v: f64, wrap_u: bool) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        // texel centers sit at half-integer coordinates
+        let x = u.clamp(0.0, 1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * height as f64 - 0.5;
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (tx, ty) = (x - x0, y - y0);
+        let column = |x: f64| if wrap_u {
+            (x as i64).rem_euclid(width as i64) as usize
+        } else {
+            (x.max(0.0) as usize).min(width - 1)
+        };
+        let clamp_y = |y: f64| (y.max(0.0) as usize).min(height - 1);
+        let (x0, x1) = (column(x0), column(x0 + 1.0));
+        let (y0, y1) = (clamp_y(y0), clamp_y(y0 + 1.0));
+
+        let top = (1.0 - tx) * *self.image.get(x0, y0) + tx * *self.image.get(x1, y0);
+        let bottom = (1.0 - tx) * *self.image.get(x0, y1) + tx * *self.image.get(x1, y1);
+        (1.0 - ty) * top + ty * bottom
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        self.bilinear(u, v, false)
+    }
+}
+
+fn decode_image(data: &[u8]) -> Result<Image, TextureError> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => decode_png(data),
+        [b'P', b'3' | b'6', ..] => decode_ppm(data),
+        [b'#', b'?', ..] => hdr::decode(data).map_err(|e| TextureError::Format(e.to_string())),
+        [0xff, 0xd8, ..] => Err(TextureError::Format("JPEG images are not supported, convert to PNG".to_string())),
+        _ => Err(TextureError::Format("unrecognized image format".to_string())),
+    }
+}
+
+#[cfg(feature = "png")]
+fn decode_png(data: &[u8]) -> Result<Image, TextureError> {
+    let decoded = png::decode(data).map_err(|e| TextureError::Format(e.to_string()))?;
+    Ok(Image::from_rgb8(decoded.width, decoded.height, &decoded.rgb))
+}
+
+#[cfg(not(feature = "png"))]
+fn decode_png(_data: &[u8]) -> Result<Image, TextureError> {
+    Err(TextureError::Format("PNG support is disabled, enable the `png` feature".to_string()))
+}
+
+/// Reads `P3` and `P6` PPM files with a maximum value of up to 255.
+fn decode_ppm(data: &[u8]) -> Result<Image, TextureError> {
+    let err = |message: &str| TextureError::Format(format!("PPM: {}", message));
+
+    // header: magic, width, height and maximum value, with `#` comments
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        match data.get(pos) {
+            None => return Err(err("truncated header")),
+            Some(b'#') => {
+                while data.get(pos).is_some_and(|&b| b != b'\n') {
+                    pos += 1;
+                }
+            },
+            Some(b) if b.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start = pos;
+                while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    pos += 1;
+                }
+                fields.push(&data[start..pos]);
+            },
+        }
+    }
+
+    let number = |field: &[u8]| {
+        std::str::from_utf8(field).ok().and_then(|s| s.parse::<usize>().ok()).ok_or_else(|| err("bad header number"))
+    };
+    let (width, height, max) = (number(fields[1])?, number(fields[2])?, number(fields[3])?);
+    if width == 0 || height == 0 {
+        return Err(err("empty image"));
+    }
+    if !(1..=255).contains(&max) {
+        return Err(err("only 8-bit images are supported"));
+    }
+
+    // a sample takes at least one byte in P6, and a digit and a separator
+    // in P3, so sizes the data can't hold are rejected before allocating
+    let available = match fields[0] {
+        b"P6" => (data.len() - pos).saturating_sub(1),
+        _ => (data.len() - pos) / 2,
+    };
+    let count = width.checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .filter(|&count| count <= available)
+        .ok_or_else(|| err("image size exceeds the pixel data"))?;
+    let mut rgb = Vec::with_capacity(count);
+    if fields[0] == b"P6" {
+        // exactly one whitespace byte separates the header from the samples
+        let start = pos + 1;
+        let samples = data.get(start..start + count).ok_or_else(|| err("not enough pixel data"))?;
+        rgb.extend_from_slice(samples);
+    } else {
+        let text = std::str::from_utf8(&data[pos..]).map_err(|_| err("invalid text"))?;
+        for token in text.split_whitespace().take(count) {
+            rgb.push(token.parse::<u8>().map_err(|_| err("bad sample value"))?);
+        }
+        if rgb.len() != count {
+            return Err(err("not enough pixel data"));
+        }
+    }
+
+    if max != 255 {
+        for sample in &mut rgb {
+            *sample = (*sample as usize * 255 / max).min(255) as u8;
+        }
+    }
+    Ok(Image::from_rgb8(width, height, &rgb))
+}
@@ -0,0 +1,89 @@
+use crate::ray::{Ray, HitRecord, Hittable};
+use crate::material::{Material};
+use std::sync::{Arc};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb, AABB_PADDING};
+
+const EPSILON: f64 = 1e-12;
+
+pub struct Triangle {
+    vertices: [Point; 3],
+    normals: Option<[Vec3; 3]>,
+    mat: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Point, b: Point, c: Point, m: Arc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            mat: m,
+        }
+    }
+
+    /// Uses per-vertex normals, interpolated across the face, for shading.
+    pub fn with_normals(mut self, na: Vec3, nb: Vec3, nc: Vec3) -> Triangle {
+        self.normals = Some([na.unit(), nb.unit(), nc.unit()]);
+        self
+    }
+
+    pub fn vertices(&self) -> &[Point; 3] {
+        &self.vertices
+    }
+}
+
+impl Hittable for Triangle {
+    /// Möller–Trumbore intersection. The hit's `(u, v)` are the barycentric
+    /// weights of the second and third vertex.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let [a, b, c] = self.vertices;
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = ray.direct().cross(&edge2);
+        let det = edge1.dot(&p);
+        // the ray is parallel to the triangle's plane
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = *ray.org() - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = ray.direct().dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let geometric = edge1.cross(&edge2).unit();
+        let front_face = ray.direct().dot(&geometric) < 0.0;
+        let mut normal = match self.normals {
+            Some([na, nb, nc]) => ((1.0 - u - v) * na + u * nb + v * nc).unit(),
+            None => geometric,
+        };
+        if !front_face { normal = -normal; }
+
+        Some(HitRecord::new(
+            t,
+            ray.range(t),
+            normal,
+            front_face,
+            Arc::clone(&self.mat),
+        ).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        Aabb::new(a, b).surrounding(&Aabb::new(c, c)).padded(AABB_PADDING)
+    }
+}
@@ -1,14 +1,20 @@
-use std::ops::{Index, IndexMut, Add, Sub, Mul, Div};
+use std::ops::{Index, IndexMut, Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign};
 use std::fmt;
-use rand::Rng;
+use crate::random::{random_f64, random_range};
+use crate::lanes::{self, Lanes};
+use std::f64::consts::PI;
 
 const DIMENSION: usize = 3;
+// per-component tolerance of `near_zero`
+const NEAR_ZERO: f64 = 1e-8;
 
 pub type Point = Vec3;
 
+/// Three `f64` components; packed into a SIMD register with the `simd`
+/// feature.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Vec3 {
-    e: [f64; DIMENSION]
+    e: Lanes,
 }
 
 impl Index<usize> for Vec3 {
@@ -17,7 +23,7 @@ impl Index<usize> for Vec3 {
         if index >= DIMENSION {
             panic!("Index {} out of range", index);
         }    
-        &self.e[index]
+        &lanes::components(&self.e)[index]
     }
 }
 
@@ -26,7 +32,7 @@ impl IndexMut<usize> for Vec3 {
         if index >= DIMENSION {
             panic!("Index {} out of range", index);
         }
-        &mut self.e[index]
+        &mut lanes::components_mut(&mut self.e)[index]
     }
 }
 
@@ -38,14 +44,13 @@ impl fmt::Display for Vec3 {
 
 impl Vec3 {
     pub const fn new(e: [f64; DIMENSION]) -> Vec3 {
-        Vec3 { e: e }
+        Vec3 { e: lanes::new(e) }
     }
 
     pub fn random(min: f64, max: f64) -> Vec3 {
         let mut result = Vec3::new([0.0; DIMENSION]);
-        let mut rng = rand::thread_rng();
         for i in 0..DIMENSION {
-            result[i] = rng.gen_range(min..max);
+            result[i] = random_range(min..max);
         }
         result
     }
@@ -55,46 +60,61 @@ impl Vec3 {
         loop {
             result = Vec3::random(-1.0, 1.0);
             result[DIMENSION - 1] = 0.0;
-            if result.square() < 1.0 { break; }
+            if result.length_squared() < 1.0 { break; }
         }
         result
     }
 
+    /// Uniformly distributed point inside the unit ball.
     pub fn random_in_unit_sphere() -> Vec3 {
         let mut result: Vec3;
         loop {
             result = Vec3::random(-1.0, 1.0);
-            if result.square() < 1.0 { break; }
+            if result.length_squared() < 1.0 { break; }
         }
         result
     }
 
-    pub fn random_unit_vec() -> Vec3 {
+    /// Uniformly distributed direction on the unit sphere.
+    pub fn random_unit_vector() -> Vec3 {
         Vec3::random_in_unit_sphere().unit()
     }
+
+    #[deprecated(note = "renamed to `random_unit_vector`")]
+    pub fn random_unit_vec() -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+
+    /// Unit direction in the `+z` hemisphere with density `cos(theta) / pi`,
+    /// to be turned into world space with an orthonormal basis.
+    pub fn random_cosine_direction() -> Vec3 {
+        let phi = 2.0 * PI * random_f64();
+        let r2 = random_f64();
+        let r = r2.sqrt();
+        Vec3::new([phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt()])
+    }
             
     pub fn x(&self) -> f64 { self[0] }
     pub fn y(&self) -> f64 { self[1] }
     pub fn z(&self) -> f64 { self[2] }
 
-    pub fn reverse(&self) ->Vec3 {
-        let mut e = [0.0; DIMENSION];
-        for i in 0..DIMENSION {
-            e[i] = -self[i];
-        }
-        Vec3 { e: e }
+    /// Same as `-v`.
+    pub fn reverse(&self) -> Vec3 {
+        -*self
+    }
+
+    /// Squared length; cheaper than `length` when only comparing sizes.
+    pub fn length_squared(&self) -> f64 {
+        self.dot(self)
     }
 
+    #[deprecated(note = "renamed to `length_squared`")]
     pub fn square(&self) -> f64 {
-        let mut res: f64 = 0.0;
-        for i in 0..DIMENSION {
-            res += self[i] * self[i];
-        }
-        res
+        self.length_squared()
     }
 
     pub fn length(&self) -> f64 {
-        self.square().sqrt()
+        self.length_squared().sqrt()
     }
 
     pub fn unit(&self) -> Vec3 {
@@ -102,41 +122,36 @@ impl Vec3 {
     }
 
     pub fn dot(&self, rhs: &Vec3) -> f64 {
-        let mut result = 0.0;
-        for i in 0..DIMENSION {
-            result += self[i] * rhs[i];
-        }
-        result
+        lanes::dot(self.e, rhs.e)
     }
 
     pub fn cross(&self, rhs: &Vec3) -> Vec3 {
-        let mut e: [f64; DIMENSION] = [0.0; DIMENSION];
-        for i in 0..(DIMENSION - 2) {
-            e[i] = self[i + 1] * rhs[i + 2] - self[i + 2] * rhs[i + 1];
-        }
-        e[DIMENSION - 2] = self[DIMENSION - 1] * rhs[0] - self[0] * rhs[DIMENSION - 1];
-        e[DIMENSION - 1] = self[0] * rhs[1] - self[1] * rhs[0];
-        Vec3 { e: e }
+        Vec3 { e: lanes::cross(self.e, rhs.e) }
     }
 
+    /// True if every component is within `NEAR_ZERO` of 0, e.g. a scatter
+    /// direction that cancelled out and would give a degenerate ray.
     pub fn near_zero(&self) -> bool {
-        let mut result = true;
-        let threshold = 1e-8;
-        for i in 0..DIMENSION {
-            result &= self[i] < threshold;
-            if !result { break; }
-        }
-        result
+        lanes::components(&self.e).iter().all(|c| c.abs() < NEAR_ZERO)
     }
 
-    pub fn specular(&self, normal: &Vec3) -> Vec3 {
+    /// Mirror reflection about `normal`, which must be a unit vector.
+    pub fn reflect(&self, normal: &Vec3) -> Vec3 {
         *self - 2.0 * self.dot(normal) * (*normal)
     }
 
+    #[deprecated(note = "renamed to `reflect`")]
+    pub fn specular(&self, normal: &Vec3) -> Vec3 {
+        self.reflect(normal)
+    }
+
+    /// Refracts this unit direction through a surface with unit `normal`
+    /// facing against it, by Snell's law with `eta_ratio` = eta_incident /
+    /// eta_transmitted. The caller checks for total internal reflection.
     pub fn refract(&self, normal: &Vec3, eta_ratio: f64) -> Vec3 {
-        let cos_theta = normal.dot(&self.reverse()).min(1.0);
+        let cos_theta = normal.dot(&-*self).min(1.0);
         let perpendicular = eta_ratio * (*self + (*normal) * cos_theta);
-        let parallel = -((1.0 - perpendicular.square()).abs().sqrt()) * (*normal);
+        let parallel = -((1.0 - perpendicular.length_squared()).abs().sqrt()) * (*normal);
         perpendicular + parallel
     }
 }
@@ -145,43 +160,28 @@ impl Vec3 {
 impl Add<Vec3> for Vec3 {
     type Output = Vec3;
     fn add(self, rhs: Vec3) -> Self::Output {
-        let mut e: [f64; DIMENSION] = [0.0; DIMENSION];
-        for i in 0..DIMENSION {
-            e[i] = self[i] + rhs[i];
-        }
-        Vec3 { e: e }
+        Vec3 { e: lanes::add(self.e, rhs.e) }
     }
 }
-        
+
 impl Sub<Vec3> for Vec3 {
     type Output = Vec3;
     fn sub(self, rhs: Vec3) -> Self::Output {
-        self + rhs.reverse()
+        Vec3 { e: lanes::sub(self.e, rhs.e) }
     }
 }
 
-
-
 impl Mul<Vec3> for Vec3 {
     type Output = Vec3;
     fn mul(self, rhs: Vec3) -> Self::Output {
-        let mut e = [0.0; DIMENSION];
-        for i in 0..DIMENSION {
-            e[i] = self[i] * rhs[i];
-        }
-        Vec3 { e: e }
+        Vec3 { e: lanes::mul(self.e, rhs.e) }
     }
 }
 
-
 impl Add<f64> for Vec3 {
     type Output = Vec3;
     fn add(self, rhs: f64) -> Self::Output {
-        let mut e = [0.0; DIMENSION];
-        for i in 0..DIMENSION {
-            e[i] = self[i] + rhs;
-        }
-        Vec3 { e: e }
+        Vec3 { e: lanes::offset(self.e, rhs) }
     }
 }
 
@@ -195,11 +195,7 @@ impl Sub<f64> for Vec3 {
 impl Mul<f64> for Vec3 {
     type Output = Vec3;
     fn mul(self, rhs: f64) -> Self::Output {
-        let mut e = [0.0; DIMENSION];
-        for i in 0..DIMENSION {
-            e[i] = self[i] * rhs;
-        }
-        Vec3 { e: e }
+        Vec3 { e: lanes::scale(self.e, rhs) }
     }
 }
 
@@ -217,3 +213,39 @@ impl Div<f64> for Vec3 {
     }
 }
 
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Self::Output {
+        Vec3 { e: lanes::neg(self.e) }
+    }
+}
+
+impl AddAssign<Vec3> for Vec3 {
+    fn add_assign(&mut self, rhs: Vec3) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign<Vec3> for Vec3 {
+    fn sub_assign(&mut self, rhs: Vec3) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign<Vec3> for Vec3 {
+    fn mul_assign(&mut self, rhs: Vec3) {
+        *self = *self * rhs;
+    }
+}
+
+impl MulAssign<f64> for Vec3 {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign<f64> for Vec3 {
+    fn div_assign(&mut self, rhs: f64) {
+        *self *= 1.0 / rhs;
+    }
+}
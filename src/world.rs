@@ -1,14 +1,25 @@
 use crate::ray::{Ray, HitRecord, Hittable};
-use crate::vec3::{Point};
+use crate::vec3::{Point, Vec3};
+use crate::aabb::{Aabb};
+use crate::bvh::{Bvh};
+use crate::random::random_f64;
 use std::sync::{Arc};
 
-pub const INF: f64 = std::f64::INFINITY;
+pub const INF: f64 = f64::INFINITY;
 pub const ORIGIN: Point = Point::new([0.0, 0.0, 0.0]);
 
+/// Flat list of objects, tested one by one for the nearest hit. Wrap it
+/// in a BVH with `into_bvh` once it holds more than a handful.
 pub struct World {
     objects: Vec<Arc<dyn Hittable>>
 }
 
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
 impl World {
     pub fn new() -> World {
         World {
@@ -16,12 +27,36 @@ impl World {
         }
     }
 
-    pub fn add(&mut self, object: Arc<impl Hittable + 'static>) {
+    /// Adds an object; an `Arc<dyn Hittable>` can be shared with a light list.
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
-    
+
+    pub fn extend(&mut self, objects: impl IntoIterator<Item = Arc<dyn Hittable>>) {
+        self.objects.extend(objects);
+    }
+
+    pub fn clear(&mut self) {
+        self.objects.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Builds a BVH over the objects added so far.
+    pub fn into_bvh(self) -> Bvh {
+        Bvh::new(&self.objects)
+    }
 }
 
+/// The name *Ray Tracing in One Weekend* uses for `World`.
+pub type HittableList = World;
+
 impl Hittable for World {
     fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut closest = t_max;
@@ -36,4 +71,27 @@ impl Hittable for World {
 
         result
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::EMPTY, |acc, obj| acc.surrounding(&obj.bounding_box()))
+    }
+
+    /// Mixture of the objects' densities with equal weights, matching
+    /// `random`, so a whole list can serve as one light.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3, t_min: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.objects.iter().map(|obj| obj.pdf_value(origin, direction, t_min)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    /// Samples a direction towards one object picked uniformly.
+    fn random(&self, origin: &Point) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new([1.0, 0.0, 0.0]);
+        }
+        let index = ((random_f64() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+        self.objects[index].random(origin)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+use lib::{Color, EnvMap, Image, Vec3};
+
+/// Directions just either side of the seam behind the viewer, where the
+/// map's left and right edges meet, see the same blend of both edges.
+#[test]
+fn env_map_wraps_across_the_seam() {
+    let (red, blue) = (Color::rgb(1.0, 0.0, 0.0), Color::rgb(0.0, 0.0, 1.0));
+    let black = Color::rgb(0.0, 0.0, 0.0);
+    let map = EnvMap::new(Image::new(4, 1, vec![red, black, black, blue]));
+    let seam = map.color(&Vec3::new([0.0, 0.0, 1.0]));
+    let left = map.color(&Vec3::new([-1e-9, 0.0, 1.0]));
+    let right = map.color(&Vec3::new([1e-9, 0.0, 1.0]));
+    for color in [seam, left, right] {
+        assert!((color - 0.5 * (red + blue)).length() < 1e-6, "expected an even blend, got {}", color);
+    }
+}
@@ -0,0 +1,21 @@
+use lib::{clamp, luminance, Color};
+
+#[test]
+fn luminance_uses_rec_709_weights() {
+    assert_eq!(luminance(&Color::rgb(1.0, 0.0, 0.0)), 0.2126);
+    assert_eq!(luminance(&Color::rgb(0.0, 1.0, 0.0)), 0.7152);
+    assert_eq!(luminance(&Color::rgb(0.0, 0.0, 1.0)), 0.0722);
+}
+
+#[test]
+fn luminance_of_gray_is_its_level() {
+    for level in [0.0, 0.25, 1.0, 8.0] {
+        assert!((luminance(&Color::rgb(level, level, level)) - level).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn clamp_limits_each_channel() {
+    let c = clamp(Color::rgb(-0.5, 0.5, 3.0), 0.0, 1.0);
+    assert_eq!(c, Color::rgb(0.0, 0.5, 1.0));
+}
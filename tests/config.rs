@@ -0,0 +1,62 @@
+use lib::{Background, Color, EnvMap, Image, RenderConfig, ToneMap};
+
+fn round_trip(config: &RenderConfig) -> RenderConfig {
+    RenderConfig::parse(&config.to_json().unwrap()).unwrap()
+}
+
+#[test]
+fn default_config_round_trips() {
+    assert_eq!(round_trip(&RenderConfig::default()), RenderConfig::default());
+}
+
+#[test]
+fn every_field_round_trips() {
+    let config = RenderConfig {
+        width: 640,
+        height: Some(480),
+        samples: 32,
+        max_depth: 6,
+        threads: Some(3),
+        tone_map: ToneMap::Aces,
+        exposure: 1.25,
+        background: Background::SolidColor(Color::rgb(0.1, 0.2, 0.3)),
+        seed: Some(7),
+    };
+    assert_eq!(round_trip(&config), config);
+
+    let gradient = RenderConfig { background: Background::VerticalGradient(Color::rgb(1.0, 1.0, 1.0), Color::rgb(0.0, 0.5, 1.0)), ..config };
+    assert_eq!(round_trip(&gradient), gradient);
+}
+
+#[test]
+fn seeds_beyond_json_numbers_round_trip() {
+    for seed in [(1 << 53) - 1, 1 << 53, (1 << 53) + 1, u64::MAX] {
+        let config = RenderConfig { seed: Some(seed), ..RenderConfig::default() };
+        assert_eq!(round_trip(&config).seed, Some(seed));
+    }
+}
+
+#[test]
+fn inexact_numeric_seed_is_rejected() {
+    assert!(RenderConfig::parse(r#"{ "seed": 18446744073709551615 }"#).is_err());
+    assert_eq!(RenderConfig::parse(r#"{ "seed": "18446744073709551615" }"#).unwrap().seed, Some(u64::MAX));
+}
+
+#[test]
+fn missing_keys_keep_defaults() {
+    let config = RenderConfig::parse(r#"{ "samples": 4, "tone_map": "reinhard" }"#).unwrap();
+    assert_eq!(config, RenderConfig { samples: 4, tone_map: ToneMap::Reinhard, ..RenderConfig::default() });
+}
+
+#[test]
+fn unknown_keys_are_errors() {
+    assert!(RenderConfig::parse(r#"{ "sample": 4 }"#).is_err());
+    assert!(RenderConfig::parse(r#"{ "tone_map": "filmic" }"#).is_err());
+}
+
+#[test]
+fn environment_background_cannot_be_written() {
+    let map = EnvMap::new(Image::new(2, 1, vec![Color::rgb(1.0, 1.0, 1.0); 2]));
+    let config = RenderConfig { background: Background::Environment(map), ..RenderConfig::default() };
+    assert!(config.to_json().is_none());
+}
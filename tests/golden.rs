@@ -0,0 +1,58 @@
+//! Renders `reference_scene` and compares it with the checked-in golden
+//! image, so refactors that change the output don't go unnoticed.
+//!
+//! After an intentional change to the output, regenerate the golden with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and commit the new image.
+
+use lib::{reference_scene, Image, ImageTexture, OutputFormat, PpmFormat};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+const GOLDEN: &str = "tests/golden/reference.ppm";
+
+/// Mean absolute difference per channel, on the 0 to 1 scale, that still
+/// passes. A seeded render is exact on one machine; this leaves room for
+/// floating-point differences between platforms while still catching a
+/// 5% change in the light or a slightly different albedo on one sphere.
+const TOLERANCE: f64 = 0.0005;
+
+fn mean_absolute_error(a: &Image, b: &Image) -> f64 {
+    let sum: f64 = a.pixels().iter().zip(b.pixels())
+        .map(|(p, q)| (p.x() - q.x()).abs() + (p.y() - q.y()).abs() + (p.z() - q.z()).abs())
+        .sum();
+    sum / (3 * a.pixels().len()) as f64
+}
+
+#[test]
+fn reference_scene_matches_the_golden_image() {
+    let (camera, scene) = reference_scene();
+    let camera = camera.build();
+    let image = camera.render_to_buffer(&scene).unwrap();
+
+    // written out so a failing render can be looked at
+    let actual = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reference.ppm");
+    let mut out = BufWriter::new(File::create(&actual).unwrap());
+    image.write(&mut out, OutputFormat::Ppm(PpmFormat::Binary), camera.output_settings()).unwrap();
+    drop(out);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::copy(&actual, GOLDEN).unwrap();
+        return;
+    }
+
+    let expected = ImageTexture::load_linear(GOLDEN).expect("golden image missing; run with UPDATE_GOLDEN=1");
+    let actual_image = ImageTexture::load_linear(&actual).unwrap();
+    let (expected, actual_image) = (expected.image(), actual_image.image());
+    assert_eq!(
+        (actual_image.width(), actual_image.height()),
+        (expected.width(), expected.height()),
+        "render size changed",
+    );
+    let error = mean_absolute_error(actual_image, expected);
+    assert!(
+        error <= TOLERANCE,
+        "render differs from {} by {:.5} on average (tolerance {}); see {}",
+        GOLDEN, error, TOLERANCE, actual.display(),
+    );
+}
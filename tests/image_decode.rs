@@ -0,0 +1,145 @@
+//! Loading malformed image files must fail with an error, never panic or
+//! try to allocate what a header claims but the file can't hold.
+
+use lib::{Color, Image, ImageTexture, OutputSettings, PpmFormat, TextureError};
+use std::fs;
+
+/// Writes `data` to a file of its own in the temp directory and loads it.
+fn load(name: &str, data: &[u8]) -> Result<ImageTexture, TextureError> {
+    let path = std::env::temp_dir().join(format!("ray-tracing-{}-{}", std::process::id(), name));
+    fs::write(&path, data).unwrap();
+    let texture = ImageTexture::load(&path);
+    let _ = fs::remove_file(&path);
+    texture
+}
+
+/// A PNG with the given IHDR fields whose IDAT holds an empty zlib stream;
+/// the decoder doesn't check CRCs, so they are left zero.
+#[cfg(feature = "png")]
+fn png(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut chunk = |kind: &[u8], body: &[u8]| {
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data.extend_from_slice(&[0; 4]);
+    };
+    let mut header = [width.to_be_bytes(), height.to_be_bytes()].concat();
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    chunk(b"IHDR", &header);
+    chunk(b"IDAT", &[0x78, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01]);
+    chunk(b"IEND", &[]);
+    data
+}
+
+#[test]
+fn valid_ppm_loads() {
+    let texture = load("valid.ppm", b"P6 2 1 255\n\xff\x00\x00\x00\x00\xff").unwrap();
+    assert_eq!((texture.image().width(), texture.image().height()), (2, 1));
+    let texture = load("valid-ascii.ppm", b"P3\n# comment\n1 1 255\n0 128 255\n").unwrap();
+    assert_eq!((texture.image().width(), texture.image().height()), (1, 1));
+}
+
+#[test]
+fn ppm_size_overflow_is_an_error() {
+    assert!(load("overflow.ppm", b"P6 99999999999 99999999999 255\n\0\0\0").is_err());
+    assert!(load("overflow-ascii.ppm", b"P3 99999999999 99999999999 255\n0 0 0").is_err());
+}
+
+#[test]
+fn ppm_size_beyond_the_data_is_an_error() {
+    assert!(load("huge.ppm", b"P6 1000000 1000000 255\n\0\0\0").is_err());
+    assert!(load("huge-ascii.ppm", b"P3 1000000 1000000 255\n0 0 0").is_err());
+    assert!(load("short.ppm", b"P6 2 1 255\n\0\0\0\0\0").is_err());
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn png_size_overflow_is_an_error() {
+    assert!(load("overflow.png", &png(u32::MAX, u32::MAX, 16, 6)).is_err());
+    assert!(load("huge.png", &png(1_000_000, 1_000_000, 8, 2)).is_err());
+}
+
+/// A 16x8 RGB PNG compressed by zlib with fixed Huffman codes, its rows
+/// using every filter type, so decoding goes beyond stored blocks.
+#[cfg(feature = "png")]
+const COMPRESSED_PNG: &[u8] = include_bytes!("images/compressed.png");
+
+#[cfg(feature = "png")]
+#[test]
+fn compressed_png_loads() {
+    let texture = load("compressed.png", COMPRESSED_PNG).unwrap();
+    assert_eq!((texture.image().width(), texture.image().height()), (16, 8));
+}
+
+/// Xorshift, so every run tries the same inputs and a failure reproduces.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A small image in every format the loader reads, with the HDR one
+/// holding run-length encoded scanlines.
+fn valid_files() -> Vec<(&'static str, Vec<u8>)> {
+    let pixels = (0..24).map(|i| Color::rgb(i as f64 / 24.0, 0.5, 1.0 - i as f64 / 24.0)).collect();
+    let image = Image::new(8, 3, pixels);
+    let settings = OutputSettings::default();
+    let mut files = Vec::new();
+    for (name, format) in [("fuzz.ppm", PpmFormat::Binary), ("fuzz-ascii.ppm", PpmFormat::Ascii)] {
+        let mut data = Vec::new();
+        image.write_ppm(&mut data, format, &settings).unwrap();
+        files.push((name, data));
+    }
+    let mut flat = Vec::new();
+    image.write_hdr(&mut flat).unwrap();
+    files.push(("fuzz-flat.hdr", flat));
+    // one run of 8 per channel on each scanline
+    let mut rle = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X 8\n".to_vec();
+    for row in 0..3u8 {
+        rle.extend_from_slice(&[2, 2, 0, 8]);
+        for channel in [100 + row, 50, 20, 129] {
+            rle.extend_from_slice(&[128 + 8, channel]);
+        }
+    }
+    files.push(("fuzz-rle.hdr", rle));
+    #[cfg(feature = "png")]
+    {
+        let mut data = Vec::new();
+        image.write_png(&mut data, &settings).unwrap();
+        files.push(("fuzz.png", data));
+        files.push(("fuzz-compressed.png", COMPRESSED_PNG.to_vec()));
+    }
+    files
+}
+
+/// Truncated files and files with bytes overwritten at random must load or
+/// fail with an error; a panic fails the test.
+#[test]
+fn corrupted_files_never_panic() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for (name, valid) in valid_files() {
+        load(name, &valid).unwrap();
+        for _ in 0..300 {
+            let mut data = valid.clone();
+            if rng.below(4) == 0 {
+                data.truncate(rng.below(valid.len()));
+            } else {
+                for _ in 0..1 + rng.below(4) {
+                    let at = rng.below(data.len());
+                    data[at] = rng.next() as u8;
+                }
+            }
+            let _ = load(name, &data);
+        }
+    }
+}
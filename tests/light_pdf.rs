@@ -0,0 +1,27 @@
+use lib::{Color, DiffuseLight, Disk, Hittable, Point, Quad, Sphere, Translate, Vec3};
+use std::sync::Arc;
+
+fn light() -> Arc<DiffuseLight> {
+    Arc::new(DiffuseLight::new(Color::new([1.0, 1.0, 1.0])))
+}
+
+/// Lights a tenth of a millimeter away, as in a scene modeled at a small
+/// scale, are only seen by the probe when `t_min` is below that distance.
+#[test]
+fn pdf_value_honors_t_min() {
+    let down = Vec3::new([0.0, -1.0, 0.0]);
+    let lights: Vec<(&str, Arc<dyn Hittable>)> = vec![
+        ("quad", Arc::new(Quad::new(Point::new([-1e-4, -1e-4, -1e-4]), Vec3::new([2e-4, 0.0, 0.0]), Vec3::new([0.0, 0.0, 2e-4]), light()))),
+        ("disk", Arc::new(Disk::new(Point::new([0.0, -1e-4, 0.0]), Vec3::new([0.0, 1.0, 0.0]), 1e-4, light()))),
+        ("sphere", Arc::new(Sphere::new(Point::new([0.0, -2e-4, 0.0]), 1e-4, light()))),
+        ("translated quad", Arc::new(Translate::new(
+            Arc::new(Quad::new(Point::new([-1e-4, 0.0, -1e-4]), Vec3::new([2e-4, 0.0, 0.0]), Vec3::new([0.0, 0.0, 2e-4]), light())),
+            Vec3::new([0.0, -1e-4, 0.0]),
+        ))),
+    ];
+    for (name, light) in lights {
+        let origin = Point::new([0.0, 0.0, 0.0]);
+        assert!(light.pdf_value(&origin, &down, 1e-6) > 0.0, "{} missed with a small t_min", name);
+        assert_eq!(light.pdf_value(&origin, &down, 1e-3), 0.0, "{} seen behind t_min", name);
+    }
+}
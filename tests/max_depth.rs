@@ -0,0 +1,56 @@
+use lib::{ray_color, Background, BoxPrim, Color, Lambertian, Metal, Point, Ray, Sphere, TraceSettings, Vec3, World};
+use std::sync::Arc;
+
+fn assert_close(actual: Color, expected: Color) {
+    assert!((actual - expected).length() < 1e-9, "expected {}, got {}", expected, actual);
+}
+
+fn uniform_sky() -> TraceSettings {
+    TraceSettings::new(Background::SolidColor(Color::new([1.0, 1.0, 1.0])))
+}
+
+#[test]
+fn max_depth_counts_bounces() {
+    let albedo = Color::new([0.5, 0.5, 0.5]);
+    let mut world = World::new();
+    world.add(Arc::new(Sphere::new(Point::new([0.0, 0.0, 0.0]), 1.0, Arc::new(Lambertian::new(albedo)))));
+    let ray = Ray::new(Point::new([0.0, 0.0, 5.0]), Vec3::new([0.0, 0.0, -1.0]));
+
+    // the sphere itself is dark; only its one bounce into the sky lights it
+    assert_close(ray_color(&ray, &world, &uniform_sky().max_depth(0)), Color::new([0.0, 0.0, 0.0]));
+    assert_close(ray_color(&ray, &world, &uniform_sky().max_depth(1)), albedo);
+}
+
+#[test]
+fn hall_of_mirrors_converges_to_the_terminal_color() {
+    // a closed box of perfect mirrors: no ray from inside ever escapes
+    let mirror = Arc::new(Metal::new(Color::new([1.0, 1.0, 1.0]), 0.0));
+    let mut world = World::new();
+    world.add(Arc::new(BoxPrim::new(Point::new([-1.0, -1.0, -1.0]), Point::new([1.0, 1.0, 1.0]), mirror)));
+    let ray = Ray::new(Point::new([0.1, 0.2, 0.3]), Vec3::new([0.3, -0.5, 0.8]));
+
+    let ambient = Color::new([0.3, 0.4, 0.5]);
+    for depth in [1, 2, 10, 50, 200] {
+        let black = ray_color(&ray, &world, &uniform_sky().max_depth(depth));
+        assert_close(black, Color::new([0.0, 0.0, 0.0]));
+        let settings = uniform_sky().max_depth(depth).terminal_color(ambient);
+        assert_close(ray_color(&ray, &world, &settings), ambient);
+    }
+}
+
+#[test]
+fn terminal_color_is_weighted_by_the_path() {
+    // each mirror bounce keeps half the light, so the cut-off path carries
+    // the terminal color scaled by 0.5 for every bounce it made
+    let mirror = Arc::new(Metal::new(Color::new([0.5, 0.5, 0.5]), 0.0));
+    let mut world = World::new();
+    world.add(Arc::new(BoxPrim::new(Point::new([-1.0, -1.0, -1.0]), Point::new([1.0, 1.0, 1.0]), mirror)));
+    let ray = Ray::new(Point::new([0.1, 0.2, 0.3]), Vec3::new([0.3, -0.5, 0.8]));
+
+    let ambient = Color::new([1.0, 1.0, 1.0]);
+    for depth in [0, 1, 4] {
+        let settings = uniform_sky().max_depth(depth).terminal_color(ambient);
+        let expected = 0.5f64.powi(depth as i32 + 1);
+        assert_close(ray_color(&ray, &world, &settings), Color::new([expected; 3]));
+    }
+}
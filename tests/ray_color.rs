@@ -0,0 +1,60 @@
+use lib::{ray_color, Background, Color, DiffuseLight, Lambertian, Point, Ray, Sphere, TraceSettings, Vec3, World};
+use std::sync::Arc;
+
+fn assert_close(actual: Color, expected: Color) {
+    assert!((actual - expected).length() < 1e-9, "expected {}, got {}", expected, actual);
+}
+
+fn sphere_world(sphere: Sphere) -> World {
+    let mut world = World::new();
+    world.add(Arc::new(sphere));
+    world
+}
+
+#[test]
+fn miss_returns_the_sky_gradient() {
+    let world = World::new();
+    let settings = TraceSettings::default();
+    let up = Ray::new(Point::new([0.0, 0.0, 0.0]), Vec3::new([0.0, 1.0, 0.0]));
+    let down = Ray::new(Point::new([0.0, 0.0, 0.0]), Vec3::new([0.0, -1.0, 0.0]));
+    assert_close(ray_color(&up, &world, &settings), Color::new([0.5, 0.7, 1.0]));
+    assert_close(ray_color(&down, &world, &settings), Color::new([1.0, 1.0, 1.0]));
+}
+
+#[test]
+fn miss_returns_a_solid_background() {
+    let world = World::new();
+    let background = Color::new([0.2, 0.4, 0.6]);
+    let settings = TraceSettings::new(Background::SolidColor(background));
+    let ray = Ray::new(Point::new([0.0, 0.0, 0.0]), Vec3::new([1.0, 2.0, 3.0]));
+    assert_close(ray_color(&ray, &world, &settings), background);
+}
+
+#[test]
+fn diffuse_sphere_under_uniform_sky_reflects_its_albedo() {
+    // every bounce off a convex object escapes, so one bounce lights it
+    let material = Arc::new(Lambertian::new(Color::new([0.5, 0.25, 0.125])));
+    let world = sphere_world(Sphere::new(Point::new([0.0, 0.0, 0.0]), 1.0, material));
+    let settings = TraceSettings::new(Background::SolidColor(Color::new([1.0, 1.0, 1.0])));
+    let ray = Ray::new(Point::new([0.0, 0.0, 5.0]), Vec3::new([0.0, 0.0, -1.0]));
+    for _ in 0..100 {
+        assert_close(ray_color(&ray, &world, &settings), Color::new([0.5, 0.25, 0.125]));
+    }
+}
+
+#[test]
+fn light_seen_directly_returns_its_emission() {
+    let emit = Color::new([4.0, 3.0, 2.0]);
+    let world = sphere_world(Sphere::new(Point::new([0.0, 0.0, 0.0]), 1.0, Arc::new(DiffuseLight::new(emit))));
+    let settings = TraceSettings::new(Background::SolidColor(Color::new([0.0, 0.0, 0.0])));
+    let ray = Ray::new(Point::new([0.0, 0.0, 5.0]), Vec3::new([0.0, 0.0, -1.0]));
+    assert_close(ray_color(&ray, &world, &settings), emit);
+}
+
+#[test]
+fn zero_depth_sees_only_the_background_and_lights() {
+    let world = World::new();
+    let settings = TraceSettings::default().max_depth(0);
+    let ray = Ray::new(Point::new([0.0, 0.0, 0.0]), Vec3::new([0.0, 1.0, 0.0]));
+    assert_close(ray_color(&ray, &world, &settings), Color::new([0.5, 0.7, 1.0]));
+}
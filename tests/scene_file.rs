@@ -0,0 +1,50 @@
+//! Scene files with values the renderer can't use must fail to parse with
+//! an error pointing at the field, not panic in a constructor.
+
+use lib::{parse_scene, SceneError};
+
+/// Parses a scene holding one sphere with the given material.
+fn with_material(material: &str) -> Result<(), SceneError> {
+    let source = format!(
+        r#"{{ "objects": [ {{ "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": {} }} ] }}"#,
+        material,
+    );
+    parse_scene(&source).map(|_| ())
+}
+
+fn invalid_path(result: Result<(), SceneError>) -> String {
+    match result {
+        Err(SceneError::Invalid { path, .. }) => path,
+        other => panic!("expected an invalid field, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn dielectric_needs_a_positive_finite_ior() {
+    assert!(with_material(r#"{ "type": "dielectric", "ior": 1.5 }"#).is_ok());
+    for ior in ["0", "-1.5", "1e999"] {
+        let result = with_material(&format!(r#"{{ "type": "dielectric", "ior": {} }}"#, ior));
+        assert_eq!(invalid_path(result), "objects[0].material.ior");
+    }
+}
+
+#[test]
+fn coated_needs_a_positive_finite_ior() {
+    assert!(with_material(r#"{ "type": "coated", "albedo": [0.8, 0.1, 0.1], "ior": 1.5 }"#).is_ok());
+    for ior in ["0", "-1.5", "1e999"] {
+        let result = with_material(&format!(r#"{{ "type": "coated", "albedo": [0.8, 0.1, 0.1], "ior": {} }}"#, ior));
+        assert_eq!(invalid_path(result), "objects[0].material.ior");
+    }
+}
+
+#[test]
+fn disk_needs_a_positive_radius() {
+    let disk = |radius: &str| parse_scene(&format!(
+        r#"{{ "objects": [ {{ "type": "disk", "center": [0, 0, 0], "normal": [0, 1, 0], "radius": {}, "material": {{ "type": "lambertian", "albedo": [0.5, 0.5, 0.5] }} }} ] }}"#,
+        radius,
+    )).map(|_| ());
+    assert!(disk("1").is_ok());
+    for radius in ["0", "-1"] {
+        assert_eq!(invalid_path(disk(radius)), "objects[0].radius");
+    }
+}